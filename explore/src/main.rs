@@ -1,145 +1,216 @@
-use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, env, fs, io, io::prelude::*};
+use std::{collections::HashMap, env, fs, io, io::prelude::*, path::Path};
 use strata::*;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 struct InputDocument {
     text: String,
+    #[serde(default)]
     layers: HashMap<String, Vec<ValidExtent>>,
+    /// The unit of the offsets of the layers.
+    #[serde(default = "byte_offsets")]
+    unit: OffsetUnit,
 }
 
-fn read_document(filename: &str) -> InputDocument {
-    let s = fs::read_to_string(filename).unwrap();
-    serde_json::from_str(&s).unwrap()
+fn byte_offsets() -> OffsetUnit {
+    OffsetUnit::Byte
 }
 
-fn index_document(content: &str) -> HashMap<String, Vec<ValidExtent>> {
-    let mut index = HashMap::new();
-    let mut chars = content.char_indices();
+fn read_document(filename: &str) -> InputDocument {
+    let s = fs::read_to_string(filename).unwrap();
+    let extension = Path::new(filename).extension().and_then(|e| e.to_str());
 
-    loop {
-        for _ in chars.by_ref().take_while_ref(|&(_, c)| !c.is_alphabetic()) {}
+    let (text, layers) = match extension {
+        // Only an object with a text is an input document, so that
+        // its mistakes are reported rather than indexed as raw JSON
+        Some("json") if is_input_document(&s) => {
+            return serde_json::from_str(&s).unwrap_or_else(|e| panic!("{}: {}", filename, e))
+        }
+        Some("json") => json_document(filename, s),
+        Some("jsonl") | Some("ndjson") => json_document(filename, s),
+        Some("csv") | Some("tsv") => {
+            let delimiter = if extension == Some("csv") {
+                b','
+            } else {
+                b'\t'
+            };
+            let table = DelimitedDocument::parse(s, delimiter)
+                .unwrap_or_else(|e| panic!("{}: {}", filename, e));
+            (table.text().to_string(), table.layers().clone())
+        }
+        Some("xml") => {
+            let markup =
+                MarkupDocument::parse_xml(&s).unwrap_or_else(|e| panic!("{}: {}", filename, e));
+            (markup.text().to_string(), markup.layers())
+        }
+        Some("html") | Some("htm") => {
+            let markup = MarkupDocument::parse_html(&s);
+            (markup.text().to_string(), markup.layers())
+        }
+        Some("md") | Some("markdown") => {
+            let markdown = MarkdownDocument::parse(s);
+            (markdown.text().to_string(), markdown.layers())
+        }
+        _ => match extension.and_then(code_syntax) {
+            Some(syntax) => {
+                let code = CodeDocument::parse(s, &syntax);
+                (code.text().to_string(), code.layers())
+            }
+            None => {
+                let layers = annotation_layers(filename, &s);
+                (s, layers)
+            }
+        },
+    };
 
-        let (first, last) = {
-            let mut words = chars.by_ref().take_while_ref(|&(_, c)| c.is_alphabetic());
-            (words.next(), words.last())
-        };
+    InputDocument {
+        text,
+        layers,
+        unit: OffsetUnit::Byte,
+    }
+}
 
-        let extent = match (first, last) {
-            (Some(s), Some(e)) => (s.0 as u64, e.0 as u64 + 1),
-            (Some(s), None) => (s.0 as u64, s.0 as u64 + 1),
-            (None, _) => break,
-        };
+fn is_input_document(s: &str) -> bool {
+    serde_json::from_str::<Value>(s).is_ok_and(|json| json.get("text").is_some())
+}
 
-        let word = content[(extent.0 as usize)..(extent.1 as usize)].to_lowercase();
+fn json_document(filename: &str, s: String) -> (String, HashMap<String, Vec<ValidExtent>>) {
+    let json = JsonDocument::parse(s).unwrap_or_else(|e| panic!("{}: {}", filename, e));
+    (json.text().to_string(), json.layers())
+}
 
-        index
-            .entry(word)
-            .or_insert_with(Vec::new)
-            .push((extent.0, extent.1));
+/// The syntax of source files with the extension.
+fn code_syntax(extension: &str) -> Option<CodeSyntax> {
+    match extension {
+        "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "go" | "java" | "js" | "ts" | "rs" => {
+            Some(CodeSyntax::c_like())
+        }
+        "py" => Some(CodeSyntax::python()),
+        _ => None,
     }
-
-    index
 }
 
-fn json_to_query<'a>(
-    json: &Value,
-    index: &'a HashMap<String, Vec<ValidExtent>>,
-    layers: &'a HashMap<String, Vec<ValidExtent>>,
-) -> Result<Box<dyn Algebra + 'a>, &'static str> {
-    let op: Box<dyn Algebra> = match *json {
-        Value::String(ref s) => Box::new(index.get(s).map(Vec::as_slice).unwrap_or(&[])),
-        Value::Array(ref a) => {
-            let cmd = match a.get(0) {
-                Some(Value::String(s)) => s,
-                _ => return Err("Must provide a string operation as the first array element"),
-            };
+/// Layers imported from annotation files next to a plain text file,
+/// such as `doc.ann` for `doc.txt`.
+fn annotation_layers(filename: &str, text: &str) -> HashMap<String, Vec<ValidExtent>> {
+    type Importer = fn(&str, &str) -> Result<Annotations, AnnotationError>;
+    let importers: [(&str, Importer); 3] = [
+        ("ann", Annotations::from_brat),
+        ("conllu", Annotations::from_conllu),
+        ("jsonld", Annotations::from_web_annotation),
+    ];
 
-            if cmd == "L" {
-                if let Some(Value::String(name)) = a.get(1) {
-                    let layer = layers.get(name).map(Vec::as_slice).unwrap_or(&[]);
-                    return Ok(Box::new(layer));
-                } else {
-                    return Err("bad layer request");
-                }
-            }
-
-            let lhs = a.get(1).ok_or("This command requires two arguments")?;
-            let rhs = a.get(2).ok_or("This command requires two arguments")?;
-
-            let lhs = json_to_query(lhs, index, layers)?;
-            let rhs = json_to_query(rhs, index, layers)?;
-
-            match cmd.as_str() {
-                "<" => Box::new(ContainedIn::new(lhs, rhs)),
-                ">" => Box::new(Containing::new(lhs, rhs)),
-                "/<" => Box::new(NotContainedIn::new(lhs, rhs)),
-                "/>" => Box::new(NotContaining::new(lhs, rhs)),
-                "&" => Box::new(BothOf::new(lhs, rhs)),
-                "|" => Box::new(OneOf::new(lhs, rhs)),
-                "->" => Box::new(FollowedBy::new(lhs, rhs)),
-                _ => return Err("Unknown operation"),
-            }
+    let mut layers = HashMap::new();
+    for (extension, import) in &importers {
+        let path = Path::new(filename).with_extension(extension);
+        if let Ok(source) = fs::read_to_string(&path) {
+            let annotations =
+                import(&source, text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            layers.extend(annotations.layers());
         }
-        _ => Box::new(Empty),
-    };
+    }
+    layers
+}
 
-    Ok(op)
+/// Layers that are found in the text unless the document supplies
+/// its own.
+fn automatic_layers() -> Vec<(&'static str, Box<dyn LayerExtractor>)> {
+    vec![
+        ("paragraph", Box::new(ParagraphLayer)),
+        ("sentence", Box::new(SentenceLayer::new())),
+        ("line", Box::new(LineLayer)),
+    ]
 }
 
-struct Index {
-    data: Vec<String>,
-    index: HashMap<String, Vec<ValidExtent>>,
-    layers: HashMap<String, Vec<ValidExtent>>,
+fn tokenizer() -> Pipeline<AlphabeticTokenizer> {
+    Pipeline::new(AlphabeticTokenizer).with_filter(CaseFold)
 }
 
-fn index() -> Index {
-    let mut data = Vec::new();
-    let mut index = HashMap::new();
-    let mut layers = HashMap::new();
+struct Documents {
+    texts: Vec<String>,
+    // Present when positions are token ordinals instead of bytes
+    offsets: Option<Vec<TokenOffsets>>,
+}
+
+fn index() -> (Documents, Index) {
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let by_tokens = args.first().is_some_and(|a| a == "--tokens");
+    if by_tokens {
+        args.remove(0);
+    }
+
+    let mut texts = Vec::new();
+    let mut offsets = Vec::new();
+    let mut index = Index::new();
 
-    for file in env::args().skip(1) {
+    for file in args {
         let doc = read_document(&file);
-        let doc_index = index_document(&doc.text);
+        let offsets_map = OffsetMap::new(&doc.text);
+        let unit = doc.unit;
+        let mut layers: HashMap<_, _> = doc
+            .layers
+            .into_iter()
+            .map(|(name, extents)| {
+                let extents = offsets_map
+                    .convert_extents(extents, unit, OffsetUnit::Byte)
+                    .unwrap_or_else(|e| panic!("Layer {} of {}: {}", name, file, e));
+                (name, extents)
+            })
+            .collect();
+        let text = &doc.text;
+        for (name, extractor) in automatic_layers() {
+            layers
+                .entry(name.to_string())
+                .or_insert_with(|| extractor.extract(text));
+        }
 
-        data.push(doc.text);
-        for (word, extents) in doc_index {
-            index.entry(word).or_insert_with(Vec::new).extend(extents);
+        let doc_index = if by_tokens {
+            let (doc_index, doc_offsets) = tokenizer().index_tokens(&doc.text);
+            for (name, extents) in layers {
+                index.extend_layer(name, doc_offsets.token_extents(extents));
+            }
+            offsets.push(doc_offsets);
+            doc_index
+        } else {
+            for (name, extents) in layers {
+                index.extend_layer(name, extents);
+            }
+            tokenizer().index(&doc.text)
+        };
+
+        texts.push(doc.text);
+        for (word, extents) in doc_index.terms() {
+            index.extend_term(word, extents.iter().copied());
         }
-        for (name, extents) in doc.layers {
-            layers.entry(name).or_insert_with(Vec::new).extend(extents);
+        for (layer, extents) in doc_index.layers() {
+            index.extend_layer(layer, extents.iter().copied());
         }
     }
 
     println!("=Index=");
-    for (word, extents) in &index {
+    for (word, extents) in index.terms() {
         println!("{}: {:?}", word, extents);
     }
     println!("=Layers=");
-    for (layer, extents) in &layers {
+    for (layer, extents) in index.layers() {
         println!("{}: {:?}", layer, extents);
     }
 
-    Index {
-        data,
-        index,
-        layers,
-    }
+    let offsets = if by_tokens { Some(offsets) } else { None };
+    (Documents { texts, offsets }, index)
 }
 
-fn query_stdin(
-    data: Vec<String>,
-    index: HashMap<String, Vec<ValidExtent>>,
-    layers: HashMap<String, Vec<ValidExtent>>,
-) {
+fn query_stdin(docs: Documents, index: Index) {
     let stdin = io::stdin();
+    let cache = QueryCache::new(&index, Materialization::AfterUses(2));
+    let tokenizer = tokenizer();
 
     for line in stdin.lock().lines() {
         let l = line.unwrap();
 
-        let q = match serde_json::from_str(&l) {
+        let q: Value = match serde_json::from_str(&l) {
             Ok(q) => q,
             Err(e) => {
                 println!("Error: {}", e);
@@ -147,23 +218,34 @@ fn query_stdin(
             }
         };
 
-        let op = match json_to_query(&q, &index, &layers) {
-            Ok(op) => op,
+        let query = match Query::from_json_in(&q, &index) {
+            Ok(query) => query.analyze(&tokenizer),
             Err(e) => {
                 println!("Error: {}", e);
                 continue;
             }
         };
 
-        for extent in op.iter_tau() {
+        for extent in cache.evaluate(&query).iter_tau() {
             let ex = (extent.0, extent.1);
-            let content = &data[0]; // HACK: 0 isnt right
-            println!("{:?}: {}", ex, &content[(ex.0 as usize)..(ex.1 as usize)]);
+            let content = &docs.texts[0]; // HACK: 0 isnt right
+            let bytes = match &docs.offsets {
+                Some(offsets) => match offsets[0].byte_extent(ex) {
+                    Some(bytes) => bytes,
+                    None => continue,
+                },
+                None => ex,
+            };
+            println!(
+                "{:?}: {}",
+                ex,
+                &content[(bytes.0 as usize)..(bytes.1 as usize)]
+            );
         }
     }
 }
 
 fn main() {
-    let index = index();
-    query_stdin(index.data, index.index, index.layers);
+    let (docs, index) = index();
+    query_stdin(docs, index);
 }
@@ -0,0 +1,610 @@
+use crate::{NestedLayer, OffsetError, OffsetMap, OffsetUnit, ValidExtent};
+#[cfg(feature = "serde")]
+use serde_json::Value;
+use std::{collections::HashMap, error::Error, fmt};
+
+/// A reason that annotations could not be imported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnnotationError {
+    /// A line of a brat or CoNLL-U file cannot be read. Lines are
+    /// counted from 1.
+    MalformedLine { line: usize },
+    /// The word of a CoNLL-U line is not the next text of the document
+    /// after whitespace.
+    Misaligned { line: usize },
+    /// A Web Annotation file is not JSON.
+    InvalidJson(String),
+    /// A Web Annotation, counted from 0, has a selector without the
+    /// properties it requires.
+    MalformedAnnotation { annotation: usize },
+    /// The quote of a Web Annotation selector is not in the text.
+    QuoteNotFound { annotation: usize },
+    /// An offset is outside the text or inside a character.
+    InvalidOffset(OffsetError),
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotationError::MalformedLine { line } => write!(f, "line {} is malformed", line),
+            AnnotationError::Misaligned { line } => {
+                write!(f, "the word on line {} is not the next in the text", line)
+            }
+            AnnotationError::InvalidJson(e) => write!(f, "invalid JSON: {}", e),
+            AnnotationError::MalformedAnnotation { annotation } => {
+                write!(f, "annotation {} has a malformed selector", annotation)
+            }
+            AnnotationError::QuoteNotFound { annotation } => {
+                write!(
+                    f,
+                    "the quote of annotation {} is not in the text",
+                    annotation
+                )
+            }
+            AnnotationError::InvalidOffset(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for AnnotationError {}
+
+impl From<OffsetError> for AnnotationError {
+    fn from(e: OffsetError) -> Self {
+        AnnotationError::InvalidOffset(e)
+    }
+}
+
+/// Layers imported from the standoff annotations of a text produced
+/// by other tools.
+///
+/// The layers are byte offsets into the text, with an exclusive end,
+/// and are named by the kind of annotation and its label:
+///
+/// | Layer              | Annotation                                  |
+/// |--------------------|---------------------------------------------|
+/// | `ENTITY:PERSON`    | brat text-bound annotations and Web Annotation tags |
+/// | `ATTR:Negated`     | brat attributes without a value             |
+/// | `ATTR:Level=High`  | brat attributes with a value                |
+/// | `POS:NOUN`         | CoNLL-U universal part-of-speech tags       |
+/// | `FEAT:Number=Sing` | CoNLL-U morphological features              |
+/// | `dep:nsubj`        | CoNLL-U dependency relations                |
+/// | `sentence`         | CoNLL-U sentences                           |
+///
+/// Annotations of the same layer may overlap or nest, as with nested
+/// entities, and are normalized in the same way as elements of a
+/// [`MarkupDocument`](crate::MarkupDocument).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations {
+    layers: HashMap<String, Vec<ValidExtent>>,
+}
+
+impl Annotations {
+    /// Imports a brat standoff `.ann` file for the text.
+    ///
+    /// Text-bound annotations, including each fragment of a
+    /// discontinuous one, and the attributes of those annotations
+    /// become layers. Offsets count characters, as brat does. An
+    /// attribute of an event covers the text of the event's trigger.
+    /// Other annotations, such as relations and the events themselves,
+    /// are ignored.
+    pub fn from_brat(ann: &str, text: &str) -> Result<Self, AnnotationError> {
+        let offsets = OffsetMap::new(text);
+        let mut annotations = Annotations::default();
+        let mut fragments: HashMap<&str, Vec<ValidExtent>> = HashMap::new();
+        // The trigger of each event
+        let mut triggers: HashMap<&str, &str> = HashMap::new();
+        let mut attributes = Vec::new();
+
+        for (line, content) in (1..).zip(ann.lines()) {
+            let malformed = || AnnotationError::MalformedLine { line };
+            let mut columns = content.split('\t');
+            let id = columns.next().unwrap_or("");
+
+            if id.starts_with('T') {
+                let mut span = columns.next().ok_or_else(malformed)?.splitn(2, ' ');
+                let label = span.next().ok_or_else(malformed)?;
+                let ranges = span.next().ok_or_else(malformed)?;
+
+                let mut extents = Vec::new();
+                for range in ranges.split(';') {
+                    let mut bounds = range.split(' ').map(|n| n.parse::<u64>().ok());
+                    let extent = match (bounds.next(), bounds.next(), bounds.next()) {
+                        (Some(Some(start)), Some(Some(end)), None) if start <= end => (start, end),
+                        _ => return Err(malformed()),
+                    };
+                    extents.push(offsets.convert_extent(
+                        extent,
+                        OffsetUnit::Char,
+                        OffsetUnit::Byte,
+                    )?);
+                }
+
+                annotations.add(format!("ENTITY:{}", label), extents.iter().copied());
+                fragments.insert(id, extents);
+            } else if id.starts_with('A') || id.starts_with('M') {
+                let fields: Vec<_> = columns.next().ok_or_else(malformed)?.split(' ').collect();
+                let name = match fields[..] {
+                    [name, _] => format!("ATTR:{}", name),
+                    [name, _, value] => format!("ATTR:{}={}", name, value),
+                    _ => return Err(malformed()),
+                };
+                attributes.push((line, name, fields[1]));
+            } else if id.starts_with('E') {
+                let (_, trigger) = columns
+                    .next()
+                    .and_then(|args| args.split(' ').next())
+                    .and_then(|event| event.split_once(':'))
+                    .ok_or_else(malformed)?;
+                triggers.insert(id, trigger);
+            }
+        }
+
+        for (line, name, target) in attributes {
+            let target = triggers.get(target).copied().unwrap_or(target);
+            let extents = fragments
+                .get(target)
+                .ok_or(AnnotationError::MalformedLine { line })?;
+            annotations.add(name, extents.iter().copied());
+        }
+
+        Ok(annotations)
+    }
+
+    /// Imports a CoNLL-U file of the words of the text.
+    ///
+    /// CoNLL-U has no offsets, so each word is found in the text after
+    /// the previous one, separated from it by nothing but whitespace.
+    /// The words of a multiword token, such as `do` and `n't` for
+    /// `don't`, share the extent of the token.
+    pub fn from_conllu(conllu: &str, text: &str) -> Result<Self, AnnotationError> {
+        let mut annotations = Annotations::default();
+        let mut pos = 0;
+        let mut sentence: Option<(usize, usize)> = None;
+        // The last word of the multiword token being read, and its extent
+        let mut token: Option<(u64, (usize, usize))> = None;
+
+        for (line, content) in (1..).zip(conllu.lines()) {
+            let malformed = || AnnotationError::MalformedLine { line };
+
+            if content.trim().is_empty() {
+                if let Some(extent) = sentence.take() {
+                    annotations.add("sentence".to_string(), Some(byte_extent(extent)));
+                }
+                continue;
+            }
+            if content.starts_with('#') {
+                continue;
+            }
+
+            let columns: Vec<_> = content.split('\t').collect();
+            if columns.len() != 10 {
+                return Err(malformed());
+            }
+            let (id, form) = (columns[0], columns[1]);
+
+            // Empty nodes do not appear in the text
+            if id.contains('.') {
+                continue;
+            }
+
+            let word = match id.split_once('-') {
+                Some((_, last)) => {
+                    let last = last.parse().map_err(|_| malformed())?;
+                    let extent =
+                        align(text, &mut pos, form).ok_or(AnnotationError::Misaligned { line })?;
+                    token = Some((last, extent));
+                    extent_with_sentence(&mut sentence, extent);
+                    continue;
+                }
+                None => {
+                    let id: u64 = id.parse().map_err(|_| malformed())?;
+                    match token {
+                        Some((last, extent)) if id <= last => {
+                            if id == last {
+                                token = None;
+                            }
+                            extent
+                        }
+                        _ => {
+                            token = None;
+                            align(text, &mut pos, form)
+                                .ok_or(AnnotationError::Misaligned { line })?
+                        }
+                    }
+                }
+            };
+            extent_with_sentence(&mut sentence, word);
+
+            let extent = Some(byte_extent(word));
+            let (upos, feats, deprel) = (columns[3], columns[5], columns[7]);
+            if upos != "_" {
+                annotations.add(format!("POS:{}", upos), extent);
+            }
+            if feats != "_" {
+                for feat in feats.split('|') {
+                    annotations.add(format!("FEAT:{}", feat), extent);
+                }
+            }
+            if deprel != "_" {
+                annotations.add(format!("dep:{}", deprel), extent);
+            }
+        }
+        if let Some(extent) = sentence {
+            annotations.add("sentence".to_string(), Some(byte_extent(extent)));
+        }
+
+        Ok(annotations)
+    }
+
+    /// Imports [W3C Web Annotations][] of the text, given as a single
+    /// annotation, an array of them, or an annotation page or
+    /// collection listing them in its `items`.
+    ///
+    /// Each textual body that tags or classifies its target names a
+    /// layer. A value that already names a kind of annotation, such as
+    /// `POS:NOUN`, is kept as written, and any other value is an
+    /// entity, so `PERSON` becomes `ENTITY:PERSON`. Bodies with other
+    /// purposes, such as comments, are ignored.
+    ///
+    /// Targets are found with the first `TextPositionSelector`, whose
+    /// offsets count characters, or `TextQuoteSelector` of each target.
+    /// Targets without either are ignored.
+    ///
+    /// Requires the `serde` feature.
+    ///
+    /// [W3C Web Annotations]: https://www.w3.org/TR/annotation-model/
+    #[cfg(feature = "serde")]
+    pub fn from_web_annotation(json: &str, text: &str) -> Result<Self, AnnotationError> {
+        let json: Value =
+            serde_json::from_str(json).map_err(|e| AnnotationError::InvalidJson(e.to_string()))?;
+
+        let items = match &json {
+            Value::Array(items) => items.as_slice(),
+            Value::Object(o) => match o.get("items").or_else(|| o.get("first")?.get("items")) {
+                Some(Value::Array(items)) => items.as_slice(),
+                _ => std::slice::from_ref(&json),
+            },
+            _ => return Err(AnnotationError::MalformedAnnotation { annotation: 0 }),
+        };
+
+        let offsets = OffsetMap::new(text);
+        let mut annotations = Annotations::default();
+
+        for (annotation, item) in items.iter().enumerate() {
+            let labels = web_annotation_labels(item);
+            if labels.is_empty() {
+                continue;
+            }
+
+            let mut extents = Vec::new();
+            for target in one_or_many(item.get("target")) {
+                let selector = one_or_many(target.get("selector")).find(|s| {
+                    matches!(
+                        s.get("type").and_then(Value::as_str),
+                        Some("TextPositionSelector") | Some("TextQuoteSelector")
+                    )
+                });
+                if let Some(selector) = selector {
+                    extents.push(select(selector, text, &offsets, annotation)?);
+                }
+            }
+
+            for label in labels {
+                annotations.add(label, extents.iter().copied());
+            }
+        }
+
+        Ok(annotations)
+    }
+
+    /// One layer for each label. Where annotations of the same layer
+    /// are nested, only the outermost is kept so that the layer is a
+    /// GC-list covering every annotated word;
+    /// [`nested_layers`](Self::nested_layers) keeps all of them.
+    pub fn layers(&self) -> HashMap<String, Vec<ValidExtent>> {
+        self.nested_layers()
+            .into_iter()
+            .map(|(name, nested)| (name, nested.outermost().to_vec()))
+            .collect()
+    }
+
+    /// The same layers as [`layers`](Self::layers), keeping every
+    /// annotation.
+    pub fn nested_layers(&self) -> HashMap<String, NestedLayer> {
+        self.layers
+            .iter()
+            .map(|(name, extents)| (name.clone(), NestedLayer::new(extents.iter().copied())))
+            .collect()
+    }
+
+    // Empty annotations cover no text and are dropped
+    fn add<I>(&mut self, name: String, extents: I)
+    where
+        I: IntoIterator<Item = ValidExtent>,
+    {
+        let mut extents = extents.into_iter().filter(|e| e.0 < e.1).peekable();
+        if extents.peek().is_some() {
+            self.layers.entry(name).or_default().extend(extents);
+        }
+    }
+}
+
+fn byte_extent((start, end): (usize, usize)) -> ValidExtent {
+    (start as u64, end as u64)
+}
+
+fn extent_with_sentence(sentence: &mut Option<(usize, usize)>, word: (usize, usize)) {
+    let (start, _) = sentence.unwrap_or(word);
+    *sentence = Some((start, word.1));
+}
+
+// Finds the word after whitespace at `pos`, moving past it
+fn align(text: &str, pos: &mut usize, word: &str) -> Option<(usize, usize)> {
+    let rest = &text[*pos..];
+    let start = *pos + rest.len() - rest.trim_start().len();
+    if !text[start..].starts_with(word) {
+        return None;
+    }
+    *pos = start + word.len();
+    Some((start, *pos))
+}
+
+#[cfg(feature = "serde")]
+fn one_or_many(value: Option<&Value>) -> impl Iterator<Item = &Value> {
+    let values = match value {
+        Some(Value::Array(values)) => values.as_slice(),
+        Some(value) => std::slice::from_ref(value),
+        None => &[],
+    };
+    values.iter()
+}
+
+#[cfg(feature = "serde")]
+fn web_annotation_labels(annotation: &Value) -> Vec<String> {
+    const PURPOSES: &[&str] = &["tagging", "classifying", "identifying"];
+
+    let mut values: Vec<&str> = one_or_many(annotation.get("body"))
+        .filter(|body| {
+            body.get("purpose")
+                .and_then(Value::as_str)
+                .is_none_or(|p| PURPOSES.contains(&p))
+        })
+        .filter_map(|body| body.get("value")?.as_str())
+        .collect();
+    values.extend(annotation.get("bodyValue").and_then(Value::as_str));
+
+    values
+        .into_iter()
+        .map(|value| {
+            if value.contains(':') {
+                value.to_string()
+            } else {
+                format!("ENTITY:{}", value)
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+fn select(
+    selector: &Value,
+    text: &str,
+    offsets: &OffsetMap,
+    annotation: usize,
+) -> Result<ValidExtent, AnnotationError> {
+    let malformed = AnnotationError::MalformedAnnotation { annotation };
+    let field = |name| selector.get(name).and_then(Value::as_str);
+
+    if let Some(exact) = field("exact") {
+        let (prefix, suffix) = (field("prefix").unwrap_or(""), field("suffix").unwrap_or(""));
+        let start = text
+            .match_indices(exact)
+            .map(|(i, _)| i)
+            .find(|&i| text[..i].ends_with(prefix) && text[i + exact.len()..].starts_with(suffix))
+            .ok_or(AnnotationError::QuoteNotFound { annotation })?;
+        return Ok((start as u64, (start + exact.len()) as u64));
+    }
+
+    let start = selector.get("start").and_then(Value::as_u64);
+    let end = selector.get("end").and_then(Value::as_u64);
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => {
+            Ok(offsets.convert_extent((start, end), OffsetUnit::Char, OffsetUnit::Byte)?)
+        }
+        _ => Err(malformed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEXT: &str = "Zoë Smith met the Bank of Berlin staff.";
+
+    fn layer<'a>(annotations: &Annotations, text: &'a str, name: &str) -> Vec<&'a str> {
+        annotations
+            .layers()
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|&(s, e)| &text[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn brat_entities_use_character_offsets() {
+        let ann = "T1\tPERSON 0 9\tZoë Smith\n\
+                   T2\tORG 18 32\tBank of Berlin\n\
+                   T3\tLOC 26 32\tBerlin\n\
+                   R1\tWorksFor Arg1:T1 Arg2:T2\n\
+                   A1\tNegated T3\n\
+                   A2\tConfidence T1 High\n\
+                   #1\tAnnotatorNotes T1\tcheck this\n";
+        let annotations = Annotations::from_brat(ann, TEXT).unwrap();
+
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:PERSON"), ["Zoë Smith"]);
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:ORG"), ["Bank of Berlin"]);
+        assert_eq!(layer(&annotations, TEXT, "ATTR:Negated"), ["Berlin"]);
+        assert_eq!(
+            layer(&annotations, TEXT, "ATTR:Confidence=High"),
+            ["Zoë Smith"]
+        );
+    }
+
+    #[test]
+    fn brat_event_attributes_cover_the_trigger() {
+        let ann = "T1\tPERSON 0 9\tZoë Smith\n\
+                   T2\tMeet 10 13\tmet\n\
+                   E1\tMeet:T2 Agent:T1\n\
+                   A1\tNegated E1\n\
+                   R1\tKnows Arg1:T1 Arg2:T1\n";
+        let annotations = Annotations::from_brat(ann, TEXT).unwrap();
+
+        assert_eq!(layer(&annotations, TEXT, "ATTR:Negated"), ["met"]);
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:Meet"), ["met"]);
+    }
+
+    #[test]
+    fn brat_fragments_and_nesting_are_normalized() {
+        let ann = "T1\tORG 18 32\tBank of Berlin\n\
+                   T2\tORG 26 32\tBerlin\n\
+                   T3\tPERSON 0 3;4 9\tZoë Smith\n";
+        let annotations = Annotations::from_brat(ann, TEXT).unwrap();
+
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:ORG"), ["Bank of Berlin"]);
+        assert_eq!(annotations.nested_layers()["ENTITY:ORG"].len(), 2);
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:PERSON"), ["Zoë", "Smith"]);
+    }
+
+    #[test]
+    fn invalid_brat_lines_are_rejected() {
+        assert_eq!(
+            Annotations::from_brat("T1\tPERSON 0\tZ\n", TEXT),
+            Err(AnnotationError::MalformedLine { line: 1 })
+        );
+        assert_eq!(
+            Annotations::from_brat("T1\tPERSON 5 2\tZ\n", TEXT),
+            Err(AnnotationError::MalformedLine { line: 1 })
+        );
+        assert_eq!(
+            Annotations::from_brat("A1\tNegated T9\n", TEXT),
+            Err(AnnotationError::MalformedLine { line: 1 })
+        );
+        assert!(matches!(
+            Annotations::from_brat("T1\tPERSON 0 99\tZ\n", TEXT),
+            Err(AnnotationError::InvalidOffset(
+                OffsetError::OutOfBounds { .. }
+            ))
+        ));
+    }
+
+    const CONLLU: &str = "\
+# sent_id = 1
+# text = I don't know.
+1\tI\tI\tPRON\tPRP\tCase=Nom|Number=Sing\t4\tnsubj\t_\t_
+2-3\tdon't\t_\t_\t_\t_\t_\t_\t_\t_
+2\tdo\tdo\tAUX\tVBP\t_\t4\taux\t_\t_
+3\tn't\tnot\tPART\tRB\t_\t4\tadvmod\t_\t_
+4\tknow\tknow\tVERB\tVB\t_\t0\troot\t_\tSpaceAfter=No
+4.1\tit\tit\tPRON\t_\t_\t_\t_\t4:obj\t_
+5\t.\t.\tPUNCT\t.\t_\t4\tpunct\t_\t_
+
+1\tYes\tyes\tINTJ\tUH\t_\t0\troot\t_\t_
+";
+
+    #[test]
+    fn conllu_words_are_aligned_to_the_text() {
+        let text = "I don't know.\n\nYes";
+        let annotations = Annotations::from_conllu(CONLLU, text).unwrap();
+
+        assert_eq!(layer(&annotations, text, "POS:PRON"), ["I"]);
+        assert_eq!(layer(&annotations, text, "POS:AUX"), ["don't"]);
+        assert_eq!(layer(&annotations, text, "POS:PART"), ["don't"]);
+        assert_eq!(layer(&annotations, text, "dep:nsubj"), ["I"]);
+        assert_eq!(layer(&annotations, text, "dep:root"), ["know", "Yes"]);
+        assert_eq!(layer(&annotations, text, "FEAT:Number=Sing"), ["I"]);
+        assert_eq!(
+            layer(&annotations, text, "sentence"),
+            ["I don't know.", "Yes"]
+        );
+    }
+
+    #[test]
+    fn conllu_words_missing_from_the_text_are_rejected() {
+        assert_eq!(
+            Annotations::from_conllu(CONLLU, "I do not know."),
+            Err(AnnotationError::Misaligned { line: 4 })
+        );
+        assert_eq!(
+            Annotations::from_conllu("1\tI\tI\n", "I"),
+            Err(AnnotationError::MalformedLine { line: 1 })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn web_annotations_select_by_position_or_quote() {
+        let json = r#"{
+            "type": "AnnotationPage",
+            "items": [
+                {
+                    "type": "Annotation",
+                    "body": {"type": "TextualBody", "value": "PERSON", "purpose": "tagging"},
+                    "target": {
+                        "source": "doc.txt",
+                        "selector": [
+                            {"type": "FragmentSelector", "value": "char=0,9"},
+                            {"type": "TextPositionSelector", "start": 0, "end": 9}
+                        ]
+                    }
+                },
+                {
+                    "type": "Annotation",
+                    "body": [
+                        {"type": "TextualBody", "value": "ORG"},
+                        {"type": "TextualBody", "value": "A bank", "purpose": "commenting"}
+                    ],
+                    "target": {
+                        "selector": {"type": "TextQuoteSelector", "exact": "Berlin", "prefix": "of "}
+                    }
+                },
+                {
+                    "bodyValue": "POS:DET",
+                    "target": {"selector": {"type": "TextQuoteSelector", "exact": "the"}}
+                },
+                {
+                    "body": {"value": "IGNORED"},
+                    "target": "doc.txt"
+                }
+            ]
+        }"#;
+        let annotations = Annotations::from_web_annotation(json, TEXT).unwrap();
+
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:PERSON"), ["Zoë Smith"]);
+        assert_eq!(layer(&annotations, TEXT, "ENTITY:ORG"), ["Berlin"]);
+        assert_eq!(layer(&annotations, TEXT, "POS:DET"), ["the"]);
+        assert_eq!(annotations.layers().len(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn invalid_web_annotations_are_rejected() {
+        let quote = r#"[{"bodyValue": "X",
+            "target": {"selector": {"type": "TextQuoteSelector", "exact": "Paris"}}}]"#;
+        let position = r#"[{"bodyValue": "X", "target": {"selector": {"type": "TextQuoteSelector", "exact": "Zoë"}}},
+            {"bodyValue": "X", "target": {"selector": {"type": "TextPositionSelector", "start": 3}}}]"#;
+
+        assert_eq!(
+            Annotations::from_web_annotation(quote, TEXT),
+            Err(AnnotationError::QuoteNotFound { annotation: 0 })
+        );
+        assert_eq!(
+            Annotations::from_web_annotation(position, TEXT),
+            Err(AnnotationError::MalformedAnnotation { annotation: 1 })
+        );
+        assert!(matches!(
+            Annotations::from_web_annotation("{", TEXT),
+            Err(AnnotationError::InvalidJson(_))
+        ));
+    }
+}
@@ -0,0 +1,563 @@
+use crate::{Index, NestedLayer, Tokenizer, ValidExtent};
+use std::collections::HashMap;
+
+/// A description of the lexical structure of a programming language,
+/// enough to find its comments, strings, identifiers and blocks
+/// without parsing it.
+///
+/// The description is approximate: constructs it does not describe,
+/// such as the character literals of a language where `'` also begins
+/// a lifetime, are read as ordinary text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeSyntax {
+    line_comments: Vec<String>,
+    block_comments: Vec<(String, String)>,
+    strings: Vec<StringSyntax>,
+    brackets: Vec<(char, char)>,
+    indentation: bool,
+    identifier_chars: Vec<char>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StringSyntax {
+    delimiter: String,
+    escape: Option<char>,
+    multiline: bool,
+}
+
+impl CodeSyntax {
+    /// A language without comments, strings or blocks, where every run
+    /// of letters, digits and underscores not starting with a digit is
+    /// an identifier.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The syntax shared by C, C++, C#, Java, JavaScript, Go and Rust:
+    /// `//` and `/* */` comments, strings in `"` with `\` escapes and
+    /// blocks in braces.
+    pub fn c_like() -> Self {
+        Self::new()
+            .with_line_comment("//")
+            .with_block_comment("/*", "*/")
+            .with_string("\"", Some('\\'))
+            .with_block('{', '}')
+    }
+
+    /// Python: `#` comments, strings in `'` or `"`, triple-quoted
+    /// strings that may span lines, and blocks of indented lines.
+    pub fn python() -> Self {
+        Self::new()
+            .with_line_comment("#")
+            .with_multiline_string("\"\"\"", Some('\\'))
+            .with_multiline_string("'''", Some('\\'))
+            .with_string("\"", Some('\\'))
+            .with_string("'", Some('\\'))
+            .with_indentation_blocks()
+    }
+
+    /// A comment running from the marker to the end of the line.
+    pub fn with_line_comment(mut self, start: impl Into<String>) -> Self {
+        self.line_comments.push(start.into());
+        self
+    }
+
+    /// A comment running from `open` to the next `close`.
+    pub fn with_block_comment(mut self, open: impl Into<String>, close: impl Into<String>) -> Self {
+        self.block_comments.push((open.into(), close.into()));
+        self
+    }
+
+    /// A string between two of the delimiters on one line. After the
+    /// escape character, a delimiter does not end the string. A string
+    /// not closed on its line ends with the line.
+    pub fn with_string(mut self, delimiter: impl Into<String>, escape: Option<char>) -> Self {
+        self.add_string(delimiter.into(), escape, false);
+        self
+    }
+
+    /// A string between two of the delimiters that may span lines.
+    pub fn with_multiline_string(
+        mut self,
+        delimiter: impl Into<String>,
+        escape: Option<char>,
+    ) -> Self {
+        self.add_string(delimiter.into(), escape, true);
+        self
+    }
+
+    /// A block from the opening bracket to its matching closing one.
+    pub fn with_block(mut self, open: char, close: char) -> Self {
+        self.brackets.push((open, close));
+        self
+    }
+
+    /// A block of consecutive lines indented more deeply than the line
+    /// before them, as in Python. Blank lines and lines holding only a
+    /// comment do not end a block.
+    pub fn with_indentation_blocks(mut self) -> Self {
+        self.indentation = true;
+        self
+    }
+
+    /// Characters besides letters, digits and underscores that may be
+    /// part of an identifier, such as `$` in JavaScript or `-` in Lisp.
+    pub fn with_identifier_chars<I>(mut self, chars: I) -> Self
+    where
+        I: IntoIterator<Item = char>,
+    {
+        self.identifier_chars.extend(chars);
+        self
+    }
+
+    // Longer delimiters are tried first, so that `"""` is not read as
+    // an empty string
+    fn add_string(&mut self, delimiter: String, escape: Option<char>, multiline: bool) {
+        self.strings.push(StringSyntax {
+            delimiter,
+            escape,
+            multiline,
+        });
+        self.strings
+            .sort_by_key(|s| std::cmp::Reverse(s.delimiter.len()));
+    }
+
+    fn is_identifier(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || self.identifier_chars.contains(&c)
+    }
+}
+
+/// A source file, with layers for its lexical structure.
+///
+/// | Layer        | Regions                                           |
+/// |--------------|---------------------------------------------------|
+/// | `comment`    | Comments, including their markers                 |
+/// | `string`     | String literals, including their delimiters       |
+/// | `identifier` | Identifiers and keywords outside comments and strings |
+/// | `block`      | Bracketed or indented blocks                      |
+///
+/// The text is the source itself, so the layers are byte offsets into
+/// it with an exclusive end. Brackets inside comments and strings do
+/// not open or close blocks, and unmatched brackets are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeDocument {
+    text: String,
+    layers: HashMap<String, Vec<ValidExtent>>,
+}
+
+impl CodeDocument {
+    pub fn parse(source: impl Into<String>, syntax: &CodeSyntax) -> Self {
+        let text = source.into();
+        let layers = Lexer::new(&text, syntax).run();
+        CodeDocument { text, layers }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// One layer for each kind of region. Only the outermost of
+    /// nested blocks are kept so that the layer is a GC-list holding
+    /// all of the code in blocks;
+    /// [`nested_layers`](Self::nested_layers) keeps all of them.
+    pub fn layers(&self) -> HashMap<String, Vec<ValidExtent>> {
+        self.nested_layers()
+            .into_iter()
+            .map(|(name, nested)| (name, nested.outermost().to_vec()))
+            .collect()
+    }
+
+    /// The same layers as [`layers`](Self::layers), keeping every
+    /// block.
+    pub fn nested_layers(&self) -> HashMap<String, NestedLayer> {
+        self.layers
+            .iter()
+            .map(|(name, extents)| (name.clone(), NestedLayer::new(extents.iter().copied())))
+            .collect()
+    }
+
+    /// Indexes the terms of the source along with every layer.
+    pub fn index<T>(&self, tokenizer: &T) -> Index
+    where
+        T: Tokenizer + ?Sized,
+    {
+        let mut index = tokenizer.index(&self.text);
+        for (name, extents) in self.layers() {
+            index.extend_layer(name, extents);
+        }
+        index
+    }
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    syntax: &'a CodeSyntax,
+    pos: usize,
+    layers: HashMap<String, Vec<ValidExtent>>,
+    // The open brackets, with their closing bracket
+    open: Vec<(char, usize)>,
+    // The start of each line that begins inside a comment or string
+    continued_lines: Vec<usize>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str, syntax: &'a CodeSyntax) -> Self {
+        Lexer {
+            text,
+            syntax,
+            pos: 0,
+            layers: HashMap::new(),
+            open: Vec::new(),
+            continued_lines: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> HashMap<String, Vec<ValidExtent>> {
+        while let Some(c) = self.rest().chars().next() {
+            let start = self.pos;
+
+            if self.starts_any(&self.syntax.line_comments).is_some() {
+                self.pos = self.line_end();
+                self.push("comment", start);
+            } else if let Some((open, close)) = self
+                .syntax
+                .block_comments
+                .iter()
+                .find(|(open, _)| self.rest().starts_with(open.as_str()))
+            {
+                let body = start + open.len();
+                self.pos = self.text[body..]
+                    .find(close.as_str())
+                    .map_or(self.text.len(), |i| body + i + close.len());
+                self.note_continued_lines(start);
+                self.push("comment", start);
+            } else if let Some(string) = self
+                .syntax
+                .strings
+                .iter()
+                .find(|s| self.rest().starts_with(s.delimiter.as_str()))
+            {
+                self.pos = self.string_end(string);
+                self.note_continued_lines(start);
+                self.push("string", start);
+            } else if c.is_numeric() {
+                // A number, including any suffix such as `0x1f` or `2u8`
+                let syntax = self.syntax;
+                self.skip_while(|c| syntax.is_identifier(c));
+            } else if self.syntax.is_identifier(c) {
+                let syntax = self.syntax;
+                self.skip_while(|c| syntax.is_identifier(c));
+                self.push("identifier", start);
+            } else {
+                self.pos += c.len_utf8();
+                self.bracket(c, start);
+            }
+        }
+
+        if self.syntax.indentation {
+            self.indentation_blocks();
+        }
+        self.layers
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn starts_any<'s>(&self, markers: &'s [String]) -> Option<&'s String> {
+        markers.iter().find(|m| self.rest().starts_with(m.as_str()))
+    }
+
+    fn line_end(&self) -> usize {
+        self.rest()
+            .find(['\n', '\r'])
+            .map_or(self.text.len(), |i| self.pos + i)
+    }
+
+    fn skip_while(&mut self, mut f: impl FnMut(char) -> bool) {
+        let len = self
+            .rest()
+            .find(|c| !f(c))
+            .unwrap_or_else(|| self.rest().len());
+        self.pos += len;
+    }
+
+    fn push(&mut self, layer: &str, start: usize) {
+        self.layers
+            .entry(layer.to_string())
+            .or_default()
+            .push((start as u64, self.pos as u64));
+    }
+
+    fn string_end(&self, string: &StringSyntax) -> usize {
+        let body = self.pos + string.delimiter.len();
+        let mut chars = self.text[body..].char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            let at = body + i;
+            if Some(c) == string.escape {
+                chars.next();
+            } else if self.text[at..].starts_with(string.delimiter.as_str()) {
+                return at + string.delimiter.len();
+            } else if !string.multiline && (c == '\n' || c == '\r') {
+                return at;
+            }
+        }
+        self.text.len()
+    }
+
+    fn note_continued_lines(&mut self, start: usize) {
+        let (text, end) = (self.text, self.pos);
+        self.continued_lines.extend(
+            text[start..end]
+                .match_indices('\n')
+                .map(|(i, _)| start + i + 1)
+                .filter(|&line| line < end),
+        );
+    }
+
+    fn bracket(&mut self, c: char, start: usize) {
+        let brackets = &self.syntax.brackets;
+        if let Some(&(_, close)) = brackets.iter().find(|&&(open, _)| open == c) {
+            self.open.push((close, start));
+        } else if brackets.iter().any(|&(_, close)| close == c) {
+            // Brackets opened since the matching one are unclosed
+            if let Some(i) = self.open.iter().rposition(|&(close, _)| close == c) {
+                let (_, open) = self.open[i];
+                self.open.truncate(i);
+                self.push("block", open);
+            }
+        }
+    }
+
+    // Each run of lines indented more deeply than the line before it,
+    // from the start of its first line's content to the end of its
+    // last line's content
+    fn indentation_blocks(&mut self) {
+        let comments = self.layers.get("comment").cloned().unwrap_or_default();
+        let is_comment = |offset: usize| {
+            comments
+                .binary_search_by_key(&(offset as u64), |&(start, _)| start)
+                .is_ok()
+        };
+
+        // The indentation and start of each open block
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut last_end = 0;
+        let mut blocks = Vec::new();
+        let mut line_start = 0;
+
+        for line in self.text.split_inclusive('\n') {
+            let start = line_start;
+            line_start += line.len();
+            if self.continued_lines.binary_search(&start).is_ok() {
+                continue;
+            }
+
+            let content = line.trim();
+            if content.is_empty() {
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+            let content_start = start + indent;
+            if is_comment(content_start) {
+                continue;
+            }
+
+            while open.last().is_some_and(|&(depth, _)| depth > indent) {
+                let (_, block_start) = open.pop().expect("A block is open");
+                blocks.push((block_start as u64, last_end as u64));
+            }
+            let enclosing = open.last().map_or(self.first_indent(), |&(depth, _)| depth);
+            if indent > enclosing && last_end > 0 {
+                open.push((indent, content_start));
+            }
+            last_end = content_start + content.len();
+        }
+        for (_, block_start) in open {
+            blocks.push((block_start as u64, last_end as u64));
+        }
+
+        self.layers
+            .entry("block".to_string())
+            .or_default()
+            .extend(blocks);
+    }
+
+    // Top-level lines may all be indented, as in an embedded snippet
+    fn first_indent(&self) -> usize {
+        self.text
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .map_or(0, |l| l.len() - l.trim_start().len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, AlphabeticTokenizer, Query};
+    use quickcheck::quickcheck;
+
+    const RUST: &str = r#"// Reads the config; never unwrap here
+fn read(path: &str) -> Config {
+    let text = fs::read_to_string(path).unwrap(); /* may { fail */
+    if text.is_empty() {
+        panic!("empty {config} at \"{}\"", path);
+    }
+    parse(&text)
+}
+"#;
+
+    const PYTHON: &str = r#"def run(items):
+    """Runs every item.
+
+  Even this line is inside the docstring.
+    """
+    for item in items:
+        # A comment at any indentation
+  # does not end the block
+        item.go('x')
+
+    return len(items)
+
+print(run([]))
+"#;
+
+    fn layer<'a>(doc: &'a CodeDocument, name: &str) -> Vec<&'a str> {
+        doc.nested_layers()
+            .get(name)
+            .map(|n| n.all())
+            .into_iter()
+            .flatten()
+            .map(|(s, e)| &doc.text()[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn comments_and_strings_are_found() {
+        let doc = CodeDocument::parse(RUST, &CodeSyntax::c_like());
+
+        assert_eq!(
+            layer(&doc, "comment"),
+            ["// Reads the config; never unwrap here", "/* may { fail */"]
+        );
+        assert_eq!(layer(&doc, "string"), [r#""empty {config} at \"{}\"""#]);
+    }
+
+    #[test]
+    fn identifiers_skip_comments_and_strings() {
+        let doc = CodeDocument::parse(RUST, &CodeSyntax::c_like());
+        let identifiers = layer(&doc, "identifier");
+
+        assert_eq!(&identifiers[..4], ["fn", "read", "path", "str"]);
+        assert!(identifiers.contains(&"unwrap"));
+        assert!(!identifiers.contains(&"never"));
+        assert!(!identifiers.contains(&"config"));
+    }
+
+    #[test]
+    fn braces_delimit_nested_blocks() {
+        let doc = CodeDocument::parse(RUST, &CodeSyntax::c_like());
+        let blocks = &doc.nested_layers()["block"];
+
+        assert_eq!(blocks.depth(), 2);
+        assert!(layer(&doc, "block")[0].starts_with("{\n    let text"));
+        assert_eq!(
+            &doc.layers()["block"],
+            blocks.outermost(),
+            "Only the outermost block is kept"
+        );
+    }
+
+    #[test]
+    fn unwrap_outside_comments_is_found() {
+        let doc = CodeDocument::parse(RUST, &CodeSyntax::c_like());
+        let index = doc.index(&AlphabeticTokenizer);
+
+        let query = Query::NotContainedIn(
+            Box::new(Query::Term("unwrap".into())),
+            Box::new(Query::Layer("comment".into())),
+        );
+        let found: Vec<_> = query.evaluate(&index).iter_tau().collect();
+
+        assert_eq!(index.term("unwrap").len(), 2);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].0 > RUST.find('\n').unwrap() as u64);
+    }
+
+    #[test]
+    fn indentation_delimits_python_blocks() {
+        let doc = CodeDocument::parse(PYTHON, &CodeSyntax::python());
+        let blocks = layer(&doc, "block");
+
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].starts_with("\"\"\"Runs every item."));
+        assert!(blocks[0].ends_with("return len(items)"));
+        assert_eq!(blocks[1], "item.go('x')");
+    }
+
+    #[test]
+    fn python_strings_may_span_lines() {
+        let doc = CodeDocument::parse(PYTHON, &CodeSyntax::python());
+        let strings = layer(&doc, "string");
+
+        assert!(strings[0].starts_with("\"\"\"Runs"));
+        assert!(strings[0].ends_with("\"\"\""));
+        assert_eq!(strings[1], "'x'");
+        assert_eq!(
+            layer(&doc, "comment"),
+            ["# A comment at any indentation", "# does not end the block"]
+        );
+    }
+
+    #[test]
+    fn syntax_is_configurable() {
+        let lisp = CodeSyntax::new()
+            .with_line_comment(";")
+            .with_string("\"", Some('\\'))
+            .with_block('(', ')')
+            .with_identifier_chars(vec!['-', '?']);
+        let doc = CodeDocument::parse("(define (empty? xs) ; test\n  (null? xs))", &lisp);
+
+        assert_eq!(
+            layer(&doc, "identifier"),
+            ["define", "empty?", "xs", "null?", "xs"]
+        );
+        assert_eq!(layer(&doc, "comment"), ["; test"]);
+        assert_eq!(doc.nested_layers()["block"].len(), 3);
+    }
+
+    #[test]
+    fn unmatched_brackets_are_ignored() {
+        let doc = CodeDocument::parse("} { a { b } ) c", &CodeSyntax::c_like());
+
+        assert_eq!(layer(&doc, "block"), ["{ b }"]);
+    }
+
+    #[test]
+    fn every_layer_is_a_gc_list() {
+        fn prop(raw: Vec<u8>) -> bool {
+            let alphabet = [
+                'a', '1', ' ', '\n', '"', '\'', '\\', '/', '*', '#', '{', '}', 'é',
+            ];
+            let text: String = raw
+                .iter()
+                .map(|b| alphabet[*b as usize % alphabet.len()])
+                .collect();
+
+            [CodeSyntax::c_like(), CodeSyntax::python()]
+                .iter()
+                .all(|syntax| {
+                    let doc = CodeDocument::parse(text.as_str(), syntax);
+                    doc.layers().values().all(|extents| {
+                        extents
+                            .windows(2)
+                            .all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1)
+                    })
+                })
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+}
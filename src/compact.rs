@@ -0,0 +1,529 @@
+use crate::{Algebra, Extent, Position, ValidExtent, END_EXTENT, START_EXTENT};
+use std::{convert::TryFrom, error::Error, fmt};
+
+const MAGIC: &[u8; 3] = b"SXL";
+const VERSION: u8 = 1;
+
+const GC_LIST: u8 = 0x01;
+const DISJOINT: u8 = 0x02;
+const KNOWN_FLAGS: u8 = GC_LIST | DISJOINT;
+
+const BLOCK_LEN: usize = 64;
+const SKIP_LEN: usize = 24;
+
+/// A reason that extents could not be encoded or decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactError {
+    /// The extent at the index is not after the one before it, so the
+    /// extents are not a GC-list.
+    NotGcList { index: usize },
+    /// The bytes do not start with the magic number.
+    NotCompact,
+    /// The format version is newer than this library.
+    UnsupportedVersion(u8),
+    /// Flags are set that this library does not know, or the GC-list
+    /// flag is not set.
+    UnsupportedFlags(u8),
+    /// The bytes end in the middle of the list.
+    Truncated,
+    /// The bytes do not describe a list matching their header.
+    Corrupt,
+}
+
+impl fmt::Display for CompactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactError::NotGcList { index } => {
+                write!(f, "extent {} does not come after the one before it", index)
+            }
+            CompactError::NotCompact => write!(f, "not a compact extent list"),
+            CompactError::UnsupportedVersion(v) => {
+                write!(f, "compact extent list version {} is not supported", v)
+            }
+            CompactError::UnsupportedFlags(flags) => {
+                write!(
+                    f,
+                    "compact extent list flags {:#04x} are not supported",
+                    flags
+                )
+            }
+            CompactError::Truncated => write!(f, "compact extent list is truncated"),
+            CompactError::Corrupt => write!(f, "compact extent list does not match its header"),
+        }
+    }
+}
+
+impl Error for CompactError {}
+
+/// A GC-list read in place from its compact binary form.
+///
+/// The bytes are checked once when the list is created, after which
+/// the access functions decode directly from them without
+/// allocating.
+///
+/// # Format
+///
+/// All integers are little-endian. A varint is an unsigned LEB128
+/// integer of at most ten bytes.
+///
+/// ```text
+/// magic     "SXL"
+/// version   u8, currently 1
+/// flags     u8
+/// count     varint, the number of extents
+/// skips     one entry per block of 64 extents:
+///             start   u64, of the first extent of the block
+///             end     u64, of the first extent of the block
+///             offset  u64, of the rest of the block in the body
+/// body      for each extent of a block after the first:
+///             gap     varint, from the start of the previous extent
+///             width   varint, from its own start to its end
+/// ```
+///
+/// The flag `0x01` marks a GC-list, whose starts and ends are both
+/// strictly increasing, and must be set. The flag `0x02` marks a list
+/// in which every extent ends before the next starts. Other flags are
+/// reserved.
+///
+/// The skip entries have a fixed width so that the access functions
+/// can binary search them in place, decoding at most one block.
+#[derive(Debug, Copy, Clone)]
+pub struct CompactExtents<'a> {
+    flags: u8,
+    len: usize,
+    skips: &'a [u8],
+    body: &'a [u8],
+}
+
+impl<'a> CompactExtents<'a> {
+    /// Encodes a GC-list.
+    pub fn encode(extents: &[ValidExtent]) -> Result<Vec<u8>, CompactError> {
+        let mut flags = GC_LIST | DISJOINT;
+        for (index, pair) in extents.windows(2).enumerate() {
+            let ((s1, e1), (s2, e2)) = (pair[0], pair[1]);
+            if s2 <= s1 || e2 <= e1 {
+                return Err(CompactError::NotGcList { index: index + 1 });
+            }
+            if s2 <= e1 {
+                flags &= !DISJOINT;
+            }
+        }
+        if let Some(index) = extents.iter().position(|&(s, e)| e < s) {
+            return Err(CompactError::NotGcList { index });
+        }
+
+        let mut skips = Vec::with_capacity(extents.len() / BLOCK_LEN * SKIP_LEN + SKIP_LEN);
+        let mut body = Vec::new();
+        for block in extents.chunks(BLOCK_LEN) {
+            let (start, end) = block[0];
+            skips.extend_from_slice(&start.to_le_bytes());
+            skips.extend_from_slice(&end.to_le_bytes());
+            skips.extend_from_slice(&(body.len() as u64).to_le_bytes());
+
+            for pair in block.windows(2) {
+                let ((s1, _), (s2, e2)) = (pair[0], pair[1]);
+                write_varint(&mut body, s2 - s1);
+                write_varint(&mut body, e2 - s2);
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 12 + skips.len() + body.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(flags);
+        write_varint(&mut bytes, extents.len() as u64);
+        bytes.extend_from_slice(&skips);
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    /// Checks the bytes and reads the list from them.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, CompactError> {
+        let rest = bytes
+            .strip_prefix(&MAGIC[..])
+            .ok_or(CompactError::NotCompact)?;
+        let (&version, rest) = rest.split_first().ok_or(CompactError::Truncated)?;
+        if version != VERSION {
+            return Err(CompactError::UnsupportedVersion(version));
+        }
+        let (&flags, rest) = rest.split_first().ok_or(CompactError::Truncated)?;
+        if flags & !KNOWN_FLAGS != 0 || flags & GC_LIST == 0 {
+            return Err(CompactError::UnsupportedFlags(flags));
+        }
+
+        let mut pos = 0;
+        let len = read_varint(rest, &mut pos).ok_or(CompactError::Truncated)?;
+        let len = usize::try_from(len).map_err(|_| CompactError::Corrupt)?;
+        let rest = &rest[pos..];
+
+        let skips_len = (len / BLOCK_LEN + usize::from(len % BLOCK_LEN != 0))
+            .checked_mul(SKIP_LEN)
+            .ok_or(CompactError::Corrupt)?;
+        if rest.len() < skips_len {
+            return Err(CompactError::Truncated);
+        }
+        let (skips, body) = rest.split_at(skips_len);
+
+        let list = CompactExtents {
+            flags,
+            len,
+            skips,
+            body,
+        };
+        list.validate()?;
+        Ok(list)
+    }
+
+    // Decodes every block, checking that they fill the body exactly
+    // and that the flags hold.
+    fn validate(&self) -> Result<(), CompactError> {
+        let mut previous: Option<ValidExtent> = None;
+        let mut pos = 0;
+
+        for b in 0..self.block_count() {
+            if self.offset(b) != pos as u64 {
+                return Err(CompactError::Corrupt);
+            }
+
+            let mut block = self.block(b);
+            let expected = block.remaining + 1;
+            let mut decoded = 0;
+            for (s2, e2) in &mut block {
+                decoded += 1;
+                if e2 < s2 {
+                    return Err(CompactError::Corrupt);
+                }
+                if let Some((s1, e1)) = previous {
+                    if s2 <= s1 || e2 <= e1 || (self.is_disjoint() && s2 <= e1) {
+                        return Err(CompactError::Corrupt);
+                    }
+                }
+                previous = Some((s2, e2));
+            }
+
+            if decoded != expected {
+                return Err(if block.pos >= self.body.len() {
+                    CompactError::Truncated
+                } else {
+                    CompactError::Corrupt
+                });
+            }
+            pos = block.pos;
+        }
+
+        if pos == self.body.len() {
+            Ok(())
+        } else {
+            Err(CompactError::Corrupt)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether every extent ends before the next one starts.
+    pub fn is_disjoint(&self) -> bool {
+        self.flags & DISJOINT != 0
+    }
+
+    /// The extents, in order.
+    pub fn iter(&self) -> impl Iterator<Item = ValidExtent> + 'a {
+        let list = *self;
+        (0..list.block_count()).flat_map(move |b| list.block(b))
+    }
+
+    fn block_count(&self) -> usize {
+        self.skips.len() / SKIP_LEN
+    }
+
+    fn skip(&self, b: usize, field: usize) -> u64 {
+        let at = b * SKIP_LEN + field * 8;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.skips[at..at + 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn first(&self, b: usize) -> ValidExtent {
+        (self.skip(b, 0), self.skip(b, 1))
+    }
+
+    fn offset(&self, b: usize) -> u64 {
+        self.skip(b, 2)
+    }
+
+    fn block(&self, b: usize) -> Block<'a> {
+        let len = BLOCK_LEN.min(self.len - b * BLOCK_LEN);
+        Block {
+            body: self.body,
+            pos: usize::try_from(self.offset(b)).unwrap_or(usize::MAX),
+            next: Some(self.first(b)),
+            remaining: len - 1,
+        }
+    }
+
+    // The last block whose first extent has its field at or before k
+    fn last_block<F>(&self, k: u64, field: F) -> Option<usize>
+    where
+        F: Fn(ValidExtent) -> u64,
+    {
+        let (mut low, mut high) = (0, self.block_count());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if field(self.first(mid)) <= k {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low.checked_sub(1)
+    }
+
+    // The first extent with its field at or after k
+    fn find_forwards<F>(&self, k: Position, field: F) -> Extent
+    where
+        F: Fn(ValidExtent) -> u64,
+    {
+        let k = match k {
+            Position::NegativeInfinity => u64::MIN,
+            Position::Valid(k) => k,
+            Position::PositiveInfinity => return END_EXTENT,
+        };
+        let b = self.last_block(k, &field).unwrap_or(0);
+        (b..self.block_count())
+            .flat_map(|b| self.block(b))
+            .find(|&e| field(e) >= k)
+            .map_or(END_EXTENT, Extent::from)
+    }
+
+    // The last extent with its field at or before k
+    fn find_backwards<F>(&self, k: Position, field: F) -> Extent
+    where
+        F: Fn(ValidExtent) -> u64,
+    {
+        let k = match k {
+            Position::NegativeInfinity => return START_EXTENT,
+            Position::Valid(k) => k,
+            Position::PositiveInfinity => u64::MAX,
+        };
+        self.last_block(k, &field)
+            .and_then(|b| self.block(b).take_while(|&e| field(e) <= k).last())
+            .map_or(START_EXTENT, Extent::from)
+    }
+}
+
+impl Algebra for CompactExtents<'_> {
+    fn tau(&self, k: Position) -> Extent {
+        self.find_forwards(k, |e| e.0)
+    }
+    fn tau_prime(&self, k: Position) -> Extent {
+        self.find_backwards(k, |e| e.1)
+    }
+    fn rho(&self, k: Position) -> Extent {
+        self.find_forwards(k, |e| e.1)
+    }
+    fn rho_prime(&self, k: Position) -> Extent {
+        self.find_backwards(k, |e| e.0)
+    }
+}
+
+// Decodes the extents of one block. Decoding stops early if the body
+// is malformed, which only happens before the list is validated.
+struct Block<'a> {
+    body: &'a [u8],
+    pos: usize,
+    next: Option<ValidExtent>,
+    remaining: usize,
+}
+
+impl Iterator for Block<'_> {
+    type Item = ValidExtent;
+
+    fn next(&mut self) -> Option<ValidExtent> {
+        let current = self.next.take()?;
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            let gap = read_varint(self.body, &mut self.pos);
+            let width = read_varint(self.body, &mut self.pos);
+            let start = gap.and_then(|gap| current.0.checked_add(gap));
+            self.next = start.and_then(|s| Some((s, s.checked_add(width?)?)));
+        }
+        Some(current)
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        if shift == 63 && byte > 1 {
+            return None;
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    // Builds a GC-list from arbitrary gaps and width changes
+    fn gc_list(steps: Vec<(u8, u8)>) -> Vec<ValidExtent> {
+        let mut extents = Vec::new();
+        let (mut start, mut width) = (0u64, 0u64);
+        for (gap, grow) in steps {
+            start += u64::from(gap) + 1;
+            width = width.saturating_sub(u64::from(gap)) + u64::from(grow);
+            extents.push((start, start + width));
+        }
+        extents
+    }
+
+    fn positions(extents: &[ValidExtent]) -> Vec<Position> {
+        let last = extents.last().map_or(0, |e| e.1);
+        let mut positions: Vec<_> = (0..=last + 2).map(Position::Valid).collect();
+        positions.push(Position::NegativeInfinity);
+        positions.push(Position::PositiveInfinity);
+        positions
+    }
+
+    #[test]
+    fn round_trips() {
+        fn prop(steps: Vec<(u8, u8)>) -> bool {
+            let extents = gc_list(steps);
+            let bytes = CompactExtents::encode(&extents).unwrap();
+            let list = CompactExtents::new(&bytes).unwrap();
+
+            list.len() == extents.len() && list.iter().eq(extents.iter().copied())
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+
+    #[test]
+    fn access_functions_match_the_extent_list() {
+        fn prop(steps: Vec<(u8, u8)>) -> bool {
+            let extents = gc_list(steps);
+            let bytes = CompactExtents::encode(&extents).unwrap();
+            let list = CompactExtents::new(&bytes).unwrap();
+
+            positions(&extents).into_iter().all(|k| {
+                list.tau(k) == extents.tau(k)
+                    && list.rho(k) == extents.rho(k)
+                    && list.tau_prime(k) == extents.tau_prime(k)
+                    && list.rho_prime(k) == extents.rho_prime(k)
+            })
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+
+    #[test]
+    fn access_functions_cross_blocks() {
+        let extents: Vec<_> = (0..500).map(|i| (i * 3, i * 3 + 3 + i % 2)).collect();
+        let bytes = CompactExtents::encode(&extents).unwrap();
+        let list = CompactExtents::new(&bytes).unwrap();
+
+        assert!(!list.is_disjoint());
+        for k in positions(&extents) {
+            assert_eq!(list.tau(k), extents.tau(k), "tau({:?})", k);
+            assert_eq!(list.rho(k), extents.rho(k), "rho({:?})", k);
+            assert_eq!(list.tau_prime(k), extents.tau_prime(k), "tau'({:?})", k);
+            assert_eq!(list.rho_prime(k), extents.rho_prime(k), "rho'({:?})", k);
+        }
+        assert!(list.iter_tau().eq(extents.iter().copied()));
+    }
+
+    #[test]
+    fn posting_lists_are_small() {
+        let extents: Vec<_> = (0..10_000).map(|i| (i * 6, i * 6 + 4)).collect();
+        let bytes = CompactExtents::encode(&extents).unwrap();
+
+        assert!(bytes.len() < extents.len() * 3, "{} bytes", bytes.len());
+        assert!(CompactExtents::new(&bytes).unwrap().is_disjoint());
+    }
+
+    #[test]
+    fn empty_lists_find_nothing() {
+        let bytes = CompactExtents::encode(&[]).unwrap();
+        let list = CompactExtents::new(&bytes).unwrap();
+
+        assert_eq!(bytes, b"SXL\x01\x03\x00");
+        assert!(list.is_empty());
+        assert_eq!(list.tau(Position::NegativeInfinity), END_EXTENT);
+        assert_eq!(list.rho_prime(Position::PositiveInfinity), START_EXTENT);
+    }
+
+    #[test]
+    fn lists_that_are_not_gc_lists_are_not_encoded() {
+        assert_eq!(
+            CompactExtents::encode(&[(1, 5), (2, 4)]),
+            Err(CompactError::NotGcList { index: 1 })
+        );
+        assert_eq!(
+            CompactExtents::encode(&[(3, 2)]),
+            Err(CompactError::NotGcList { index: 0 })
+        );
+    }
+
+    #[test]
+    fn invalid_bytes_are_rejected() {
+        let extents: Vec<_> = (0..100).map(|i| (i * 2, i * 2)).collect();
+        let bytes = CompactExtents::encode(&extents).unwrap();
+
+        assert_eq!(
+            CompactExtents::new(b"XYZ\x01").unwrap_err(),
+            CompactError::NotCompact
+        );
+        assert_eq!(
+            CompactExtents::new(b"SXL\x02\x01\x00").unwrap_err(),
+            CompactError::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            CompactExtents::new(b"SXL\x01\x02\x00").unwrap_err(),
+            CompactError::UnsupportedFlags(2)
+        );
+        assert_eq!(
+            CompactExtents::new(&bytes[..bytes.len() - 1]).unwrap_err(),
+            CompactError::Truncated
+        );
+        assert_eq!(
+            CompactExtents::new(&bytes[..20]).unwrap_err(),
+            CompactError::Truncated
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            CompactExtents::new(&trailing).unwrap_err(),
+            CompactError::Corrupt
+        );
+
+        // A zero gap makes two extents start together
+        let mut overlapping = bytes;
+        let body = overlapping.len() - 36 * 2;
+        overlapping[body] = 0;
+        assert_eq!(
+            CompactExtents::new(&overlapping).unwrap_err(),
+            CompactError::Corrupt
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{Algebra, Extent, Position, ValidExtent, END_EXTENT, START_EXTENT};
+use std::{
+    error::Error,
+    fmt,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    time::{Duration, Instant},
+};
+
+const NOT_INTERRUPTED: u8 = 0;
+const INTERRUPTED_DEADLINE: u8 = 1;
+const INTERRUPTED_STEPS: u8 = 2;
+
+/// The reason that evaluation of a query was stopped early.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupted {
+    /// The deadline of the evaluation context passed.
+    DeadlineExceeded,
+    /// The evaluation context ran out of primitive steps.
+    StepBudgetExhausted,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupted::DeadlineExceeded => "the query deadline was exceeded".fmt(f),
+            Interrupted::StepBudgetExhausted => "the query step budget was exhausted".fmt(f),
+        }
+    }
+}
+
+impl Error for Interrupted {}
+
+/// Bounds the amount of work performed while evaluating a query.
+///
+/// The operators loop internally until they find a matching extent,
+/// so a query cannot be stopped from the outside. Instead, the leaves
+/// of the query are wrapped with [`limit`](EvaluationContext::limit)
+/// and every call to one of their access functions counts as one
+/// step. Once the deadline has passed or the step budget is spent,
+/// the wrapped leaves behave as if they were empty, which causes
+/// every operator to promptly run out of extents.
+///
+/// Because the extents found after that point are meaningless, the
+/// iterators should be wrapped with
+/// [`interruptible`](EvaluationContext::interruptible), which reports
+/// the interruption instead of silently ending early.
+///
+/// ```
+/// use strata::{Algebra, ContainedIn, EvaluationContext, Interrupted};
+///
+/// let words = [(1, 2), (3, 4), (5, 6)];
+/// let sentences = [(0, 10)];
+///
+/// let context = EvaluationContext::new().with_max_steps(3);
+/// let query = ContainedIn::new(context.limit(&words[..]), context.limit(&sentences[..]));
+/// let results: Vec<_> = context.interruptible(query.iter_tau()).collect();
+///
+/// assert_eq!(results, [Ok((1, 2)), Err(Interrupted::StepBudgetExhausted)]);
+/// ```
+#[derive(Debug, Default)]
+pub struct EvaluationContext {
+    deadline: Option<Instant>,
+    max_steps: Option<u64>,
+    steps: AtomicU64,
+    interrupted: AtomicU8,
+}
+
+impl EvaluationContext {
+    /// A context that never interrupts evaluation, but still counts
+    /// the steps taken.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop evaluation once this point in time has passed.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop evaluation once this much time has passed from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Stop evaluation after this many primitive steps.
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// The number of primitive steps attempted so far.
+    pub fn steps(&self) -> u64 {
+        self.steps.load(Ordering::Relaxed)
+    }
+
+    /// Why evaluation was stopped, if it was.
+    pub fn interrupted(&self) -> Option<Interrupted> {
+        match self.interrupted.load(Ordering::Relaxed) {
+            NOT_INTERRUPTED => None,
+            INTERRUPTED_DEADLINE => Some(Interrupted::DeadlineExceeded),
+            _ => Some(Interrupted::StepBudgetExhausted),
+        }
+    }
+
+    /// Counts the primitive steps of the algebra against this context.
+    pub fn limit<A>(&self, a: A) -> Limited<'_, A>
+    where
+        A: Algebra,
+    {
+        Limited { a, context: self }
+    }
+
+    /// Reports an interruption of this context as an error item.
+    pub fn interruptible<I>(&self, iter: I) -> Interruptible<'_, I>
+    where
+        I: Iterator<Item = ValidExtent>,
+    {
+        Interruptible {
+            iter,
+            context: self,
+            done: false,
+        }
+    }
+
+    /// Records one step, returning `false` if evaluation should stop.
+    fn step(&self) -> bool {
+        if self.interrupted().is_some() {
+            return false;
+        }
+
+        let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if self.max_steps.is_some_and(|max| steps > max) {
+            self.interrupt(INTERRUPTED_STEPS);
+            return false;
+        }
+
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            self.interrupt(INTERRUPTED_DEADLINE);
+            return false;
+        }
+
+        true
+    }
+
+    fn interrupt(&self, reason: u8) {
+        let _ = self.interrupted.compare_exchange(
+            NOT_INTERRUPTED,
+            reason,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// An algebra whose primitive steps are counted against an
+/// [`EvaluationContext`].
+#[derive(Debug, Copy, Clone)]
+pub struct Limited<'c, A> {
+    a: A,
+    context: &'c EvaluationContext,
+}
+
+impl<'c, A> Algebra for Limited<'c, A>
+where
+    A: Algebra,
+{
+    fn tau(&self, k: Position) -> Extent {
+        if !self.context.step() {
+            return END_EXTENT;
+        }
+        self.a.tau(k)
+    }
+
+    fn tau_prime(&self, k: Position) -> Extent {
+        if !self.context.step() {
+            return START_EXTENT;
+        }
+        self.a.tau_prime(k)
+    }
+
+    fn rho(&self, k: Position) -> Extent {
+        if !self.context.step() {
+            return END_EXTENT;
+        }
+        self.a.rho(k)
+    }
+
+    fn rho_prime(&self, k: Position) -> Extent {
+        if !self.context.step() {
+            return START_EXTENT;
+        }
+        self.a.rho_prime(k)
+    }
+}
+
+/// Yields the extents of the wrapped iterator until the
+/// [`EvaluationContext`] is interrupted, then yields the reason once.
+#[derive(Debug)]
+pub struct Interruptible<'c, I> {
+    iter: I,
+    context: &'c EvaluationContext,
+    done: bool,
+}
+
+impl<'c, I> Iterator for Interruptible<'c, I>
+where
+    I: Iterator<Item = ValidExtent>,
+{
+    type Item = Result<ValidExtent, Interrupted>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.iter.next();
+
+        // Any extent found while interrupted may be incorrect
+        match self.context.interrupted() {
+            Some(reason) => {
+                self.done = true;
+                Some(Err(reason))
+            }
+            None => {
+                self.done = next.is_none();
+                next.map(Ok)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ContainedIn, NotContainedIn, OneOf};
+
+    fn words(count: u64) -> Vec<ValidExtent> {
+        (0..count).map(|i| (i * 2, i * 2 + 1)).collect()
+    }
+
+    #[test]
+    fn unlimited_context_matches_unwrapped_query() {
+        let a = words(100);
+        let b = [(10, 50), (80, 120)];
+        let context = EvaluationContext::new();
+
+        let limited = ContainedIn::new(context.limit(&a[..]), context.limit(&b[..]));
+        let unlimited = ContainedIn::new(&a[..], &b[..]);
+
+        let found: Vec<_> = context.interruptible(limited.iter_tau()).collect();
+        let expected: Vec<_> = unlimited.iter_tau().map(Ok).collect();
+
+        assert_eq!(found, expected);
+        assert!(context.steps() > 0);
+        assert_eq!(context.interrupted(), None);
+    }
+
+    #[test]
+    fn step_budget_stops_long_search() {
+        // Every word is excluded, so a single `tau` call visits them all
+        let a = words(100_000);
+        let context = EvaluationContext::new().with_max_steps(1_000);
+        let c = NotContainedIn::new(context.limit(&a[..]), context.limit(&a[..]));
+
+        let found: Vec<_> = context.interruptible(c.iter_tau()).collect();
+
+        assert_eq!(found, [Err(Interrupted::StepBudgetExhausted)]);
+        assert_eq!(context.steps(), 1_001);
+    }
+
+    #[test]
+    fn step_budget_stops_iteration_in_every_direction() {
+        let a = words(1_000);
+        let b = words(1_000);
+        let context = EvaluationContext::new().with_max_steps(100);
+        let c = OneOf::new(context.limit(&a[..]), context.limit(&b[..]));
+
+        let backwards: Vec<_> = context.interruptible(c.iter_rho_prime()).collect();
+
+        assert_eq!(
+            backwards.len(),
+            1 + backwards.iter().filter(|r| r.is_ok()).count()
+        );
+        assert_eq!(
+            backwards.last(),
+            Some(&Err(Interrupted::StepBudgetExhausted))
+        );
+    }
+
+    #[test]
+    fn passed_deadline_stops_evaluation() {
+        let a = words(10);
+        let context = EvaluationContext::new().with_deadline(Instant::now());
+        let c = context.limit(&a[..]);
+
+        let found: Vec<_> = context.interruptible(c.iter_tau()).collect();
+
+        assert_eq!(found, [Err(Interrupted::DeadlineExceeded)]);
+    }
+
+    #[test]
+    fn distant_deadline_does_not_stop_evaluation() {
+        let a = words(10);
+        let context = EvaluationContext::new().with_timeout(Duration::from_secs(3600));
+        let c = context.limit(&a[..]);
+
+        assert_eq!(context.interruptible(c.iter_tau()).count(), 10);
+        assert_eq!(context.interrupted(), None);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{
+    doc_and_offset_to_k, k_to_doc_and_offset, Algebra, Epsilon, Extent, Position, ValidExtent,
+    DOC_OFFSET_MAX,
+};
+use std::collections::BTreeSet;
+
+/// A set of deleted document numbers.
+///
+/// Only the deleted documents are stored, so the size of the set does
+/// not depend on how large their numbers are.
+///
+/// With the `serde` feature, the set is serialized as a list of the
+/// deleted documents in increasing order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "Vec<u32>", into = "Vec<u32>"))]
+pub struct Deletions {
+    docs: BTreeSet<u32>,
+}
+
+impl Deletions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the document as deleted, returning `false` if it already
+    /// was.
+    pub fn insert(&mut self, doc: u32) -> bool {
+        self.docs.insert(doc)
+    }
+
+    pub fn contains(&self, doc: u32) -> bool {
+        self.docs.contains(&doc)
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// The deleted documents, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.docs.iter().copied()
+    }
+
+    /// The document that the extent lies entirely within, if that
+    /// document is deleted.
+    fn containing(&self, extent: ValidExtent) -> Option<u32> {
+        let (start_doc, _) = k_to_doc_and_offset(extent.0);
+        let (end_doc, _) = k_to_doc_and_offset(extent.1);
+
+        if start_doc == end_doc && self.contains(start_doc) {
+            Some(start_doc)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the extent lies entirely within a deleted document.
+    pub fn covers(&self, extent: ValidExtent) -> bool {
+        self.containing(extent).is_some()
+    }
+}
+
+impl From<Vec<u32>> for Deletions {
+    fn from(docs: Vec<u32>) -> Self {
+        Deletions {
+            docs: docs.into_iter().collect(),
+        }
+    }
+}
+
+impl From<Deletions> for Vec<u32> {
+    fn from(deletions: Deletions) -> Self {
+        deletions.iter().collect()
+    }
+}
+
+/// Hides the extents that lie entirely within a deleted document.
+///
+/// The wrapped algebra must produce a GC-list, as every operator
+/// does. That ordering allows all extents of a deleted document to be
+/// skipped with one call to the underlying algebra.
+#[derive(Debug, Copy, Clone)]
+pub struct SkipDeleted<'d, A> {
+    a: A,
+    deletions: &'d Deletions,
+}
+
+impl<'d, A> SkipDeleted<'d, A>
+where
+    A: Algebra,
+{
+    pub fn new(a: A, deletions: &'d Deletions) -> Self {
+        SkipDeleted { a, deletions }
+    }
+
+    fn deleted_document(&self, extent: Extent) -> Option<u32> {
+        match extent {
+            Extent(Position::Valid(p), Position::Valid(q)) => self.deletions.containing((p, q)),
+            _ => None,
+        }
+    }
+
+    // The first position after the document
+    fn after(doc: u32) -> Position {
+        Position::from(doc_and_offset_to_k(doc, DOC_OFFSET_MAX)).increment()
+    }
+
+    // The last position before the document
+    fn before(doc: u32) -> Position {
+        Position::from(doc_and_offset_to_k(doc, 0)).decrement()
+    }
+}
+
+impl<'d, A> Algebra for SkipDeleted<'d, A>
+where
+    A: Algebra,
+{
+    // Once an extent within a deleted document is found, the next
+    // candidate is the first extent ending after that document. In a
+    // GC-list it also starts after the rejected extent.
+    fn tau(&self, k: Position) -> Extent {
+        let mut extent = self.a.tau(k);
+        while let Some(doc) = self.deleted_document(extent) {
+            extent = self.a.rho(Self::after(doc));
+        }
+        extent
+    }
+
+    fn tau_prime(&self, k: Position) -> Extent {
+        let mut extent = self.a.tau_prime(k);
+        while let Some(doc) = self.deleted_document(extent) {
+            extent = self.a.rho_prime(Self::before(doc));
+        }
+        extent
+    }
+
+    fn rho(&self, k: Position) -> Extent {
+        let mut extent = self.a.rho(k);
+        while let Some(doc) = self.deleted_document(extent) {
+            extent = self.a.rho(Self::after(doc));
+        }
+        extent
+    }
+
+    fn rho_prime(&self, k: Position) -> Extent {
+        let mut extent = self.a.rho_prime(k);
+        while let Some(doc) = self.deleted_document(extent) {
+            extent = self.a.rho_prime(Self::before(doc));
+        }
+        extent
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Documents;
+
+    fn doc_extent(doc: u32, start: u32, end: u32) -> ValidExtent {
+        (
+            doc_and_offset_to_k(doc, start),
+            doc_and_offset_to_k(doc, end),
+        )
+    }
+
+    fn extents_in_documents(documents: u32) -> Vec<ValidExtent> {
+        (0..documents)
+            .flat_map(|doc| {
+                vec![
+                    doc_extent(doc, 0, 3),
+                    doc_extent(doc, 5, 8),
+                    doc_extent(doc, 9, 9),
+                ]
+            })
+            .collect()
+    }
+
+    fn deleted(docs: &[u32]) -> Deletions {
+        let mut deletions = Deletions::new();
+        for &doc in docs {
+            deletions.insert(doc);
+        }
+        deletions
+    }
+
+    fn interesting_positions(documents: u32) -> Vec<Position> {
+        let mut positions = vec![Position::NegativeInfinity, Position::PositiveInfinity];
+        for doc in 0..=documents {
+            for &offset in &[0, 1, 4, 5, 8, 9, 10, DOC_OFFSET_MAX] {
+                positions.push(doc_and_offset_to_k(doc, offset).into());
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn deletions_track_documents() {
+        let mut deletions = Deletions::new();
+
+        assert!(deletions.insert(3));
+        assert!(deletions.insert(200));
+        assert!(!deletions.insert(3));
+
+        assert!(deletions.contains(3));
+        assert!(deletions.contains(200));
+        assert!(!deletions.contains(4));
+        assert!(!deletions.contains(100_000));
+        assert_eq!(deletions.len(), 2);
+        assert!(deletions.iter().eq(vec![3, 200]));
+    }
+
+    #[test]
+    fn skip_deleted_matches_filtered_list_at_any_k() {
+        let all = extents_in_documents(6);
+        let deletions = deleted(&[0, 2, 3, 5]);
+        let expected: Vec<_> = all
+            .iter()
+            .copied()
+            .filter(|&e| !deletions.covers(e))
+            .collect();
+        let skip = SkipDeleted::new(&all[..], &deletions);
+
+        for k in interesting_positions(6) {
+            assert_eq!(skip.tau(k), expected.tau(k), "tau({:?})", k);
+            assert_eq!(skip.rho(k), expected.rho(k), "rho({:?})", k);
+            assert_eq!(
+                skip.tau_prime(k),
+                expected.tau_prime(k),
+                "tau_prime({:?})",
+                k
+            );
+            assert_eq!(
+                skip.rho_prime(k),
+                expected.rho_prime(k),
+                "rho_prime({:?})",
+                k
+            );
+        }
+    }
+
+    #[test]
+    fn skip_deleted_iterates_in_both_directions() {
+        let all = extents_in_documents(4);
+        let deletions = deleted(&[1]);
+        let skip = SkipDeleted::new(&all[..], &deletions);
+
+        assert!(skip.iter_tau().eq(skip.iter_rho()));
+        assert!(skip.iter_tau_prime().eq(skip.iter_rho_prime()));
+        assert_eq!(skip.iter_tau().count(), 9);
+    }
+
+    #[test]
+    fn skip_deleted_keeps_extents_spanning_documents() {
+        let all = [(doc_and_offset_to_k(1, 5), doc_and_offset_to_k(2, 5))];
+        let deletions = deleted(&[1, 2]);
+        let skip = SkipDeleted::new(&all[..], &deletions);
+
+        assert_eq!(skip.iter_tau().collect::<Vec<_>>(), all);
+    }
+
+    #[test]
+    fn skip_deleted_hides_deleted_documents() {
+        let deletions = deleted(&[0, 2]);
+        let skip = SkipDeleted::new(Documents::new(4), &deletions);
+
+        let found: Vec<_> = skip
+            .iter_tau()
+            .map(|(s, _)| k_to_doc_and_offset(s).0)
+            .collect();
+
+        assert_eq!(found, [1, 3]);
+    }
+}
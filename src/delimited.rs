@@ -0,0 +1,381 @@
+use crate::{Index, Tokenizer, ValidExtent, TOKEN_LAYER};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// A reason that a delimited file could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DelimitedError {
+    /// A quoted field is never closed.
+    UnclosedQuote { offset: usize },
+    /// Text follows the closing quote of a field.
+    Malformed { offset: usize },
+    /// A column of the header has the name of a layer that the
+    /// document creates itself, such as `#row`.
+    ReservedColumn { name: String },
+}
+
+impl fmt::Display for DelimitedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelimitedError::UnclosedQuote { offset } => {
+                write!(f, "quoted field at byte {} is never closed", offset)
+            }
+            DelimitedError::Malformed { offset } => {
+                write!(f, "unexpected text after a quoted field at byte {}", offset)
+            }
+            DelimitedError::ReservedColumn { name } => {
+                write!(f, "the column name {:?} is reserved", name)
+            }
+        }
+    }
+}
+
+impl Error for DelimitedError {}
+
+/// A CSV or TSV file, with layers for its rows and columns.
+///
+/// The first record is the header, naming the columns. Each later
+/// record is a region of the `#row` layer and each of its fields is a
+/// region of the layer named by the header of its column, so a column
+/// named `city` becomes the `city` layer. The header itself is the
+/// `#header` layer. Fields without a header and empty fields belong to
+/// no column layer, and blank lines are not records. A column may not
+/// be named `#header`, `#row` or [`TOKEN_LAYER`], as those layers
+/// would then mix fields with other regions.
+///
+/// The text is the file itself, so the layers are byte offsets into
+/// it with an exclusive end. A quoted field includes its quotes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelimitedDocument {
+    text: String,
+    columns: Vec<String>,
+    layers: HashMap<String, Vec<ValidExtent>>,
+}
+
+impl DelimitedDocument {
+    /// Reads comma-separated values.
+    pub fn parse_csv(source: impl Into<String>) -> Result<Self, DelimitedError> {
+        Self::parse(source, b',')
+    }
+
+    /// Reads tab-separated values.
+    pub fn parse_tsv(source: impl Into<String>) -> Result<Self, DelimitedError> {
+        Self::parse(source, b'\t')
+    }
+
+    /// Reads fields separated by the ASCII delimiter and records
+    /// separated by line breaks, as described by [RFC 4180][]. A field
+    /// may be quoted to hold the delimiter, line breaks or quotes,
+    /// which are written twice.
+    ///
+    /// [RFC 4180]: https://www.rfc-editor.org/rfc/rfc4180
+    pub fn parse(source: impl Into<String>, delimiter: u8) -> Result<Self, DelimitedError> {
+        let text = source.into();
+        let mut records = Records {
+            source: &text,
+            delimiter,
+            pos: 0,
+        };
+
+        const RESERVED: [&str; 3] = ["#header", "#row", TOKEN_LAYER];
+
+        let mut columns = Vec::new();
+        let mut layers: HashMap<String, Vec<ValidExtent>> = HashMap::new();
+
+        if let Some((header, fields)) = records.next().transpose()? {
+            columns = fields
+                .iter()
+                .map(|&(s, e)| unquote(&text[s..e]).trim().to_string())
+                .collect();
+            if let Some(name) = columns.iter().find(|c| RESERVED.contains(&c.as_str())) {
+                return Err(DelimitedError::ReservedColumn { name: name.clone() });
+            }
+            layers
+                .entry("#header".to_string())
+                .or_default()
+                .push(extent(header));
+        }
+
+        for record in records {
+            let (row, fields) = record?;
+            layers
+                .entry("#row".to_string())
+                .or_default()
+                .push(extent(row));
+
+            for (column, field) in columns.iter().zip(fields) {
+                if column.is_empty() || field.0 == field.1 {
+                    continue;
+                }
+                layers
+                    .entry(column.clone())
+                    .or_default()
+                    .push(extent(field));
+            }
+        }
+
+        Ok(DelimitedDocument {
+            text,
+            columns,
+            layers,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The names of the columns, from the header.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The `#header` and `#row` layers and one layer for each column.
+    /// Every layer is a GC-list.
+    pub fn layers(&self) -> &HashMap<String, Vec<ValidExtent>> {
+        &self.layers
+    }
+
+    /// Indexes the terms of the file along with every layer.
+    pub fn index<T>(&self, tokenizer: &T) -> Index
+    where
+        T: Tokenizer + ?Sized,
+    {
+        let mut index = tokenizer.index(&self.text);
+        for (name, extents) in &self.layers {
+            index.extend_layer(name.clone(), extents.iter().copied());
+        }
+        index
+    }
+}
+
+fn extent((start, end): (usize, usize)) -> ValidExtent {
+    (start as u64, end as u64)
+}
+
+fn unquote(field: &str) -> String {
+    match field.strip_prefix('"').and_then(|f| f.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => field.to_string(),
+    }
+}
+
+type Record = ((usize, usize), Vec<(usize, usize)>);
+
+// The extent of each non-blank record and of its fields
+struct Records<'a> {
+    source: &'a str,
+    delimiter: u8,
+    pos: usize,
+}
+
+impl Records<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn field(&mut self) -> Result<(usize, usize), DelimitedError> {
+        let start = self.pos;
+
+        if self.peek() == Some(b'"') {
+            self.pos += 1;
+            loop {
+                match self.peek() {
+                    Some(b'"') if self.source.as_bytes().get(self.pos + 1) == Some(&b'"') => {
+                        self.pos += 2
+                    }
+                    Some(b'"') => break,
+                    Some(_) => self.pos += 1,
+                    None => return Err(DelimitedError::UnclosedQuote { offset: start }),
+                }
+            }
+            self.pos += 1;
+
+            match self.peek() {
+                None | Some(b'\n' | b'\r') => {}
+                Some(b) if b == self.delimiter => {}
+                Some(_) => return Err(DelimitedError::Malformed { offset: self.pos }),
+            }
+        } else {
+            while let Some(b) = self.peek() {
+                if b == self.delimiter || b == b'\n' || b == b'\r' {
+                    break;
+                }
+                self.pos += 1;
+            }
+        }
+
+        Ok((start, self.pos))
+    }
+
+    fn record(&mut self) -> Result<Record, DelimitedError> {
+        let start = self.pos;
+        let mut fields = vec![self.field()?];
+        while self.peek() == Some(self.delimiter) {
+            self.pos += 1;
+            fields.push(self.field()?);
+        }
+        let end = self.pos;
+
+        if self.peek() == Some(b'\r') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+
+        Ok(((start, end), fields))
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<Record, DelimitedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.source.len() {
+            match self.record() {
+                Ok(((start, end), _)) if start == end => continue,
+                record => return Some(record),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, AlphabeticTokenizer, Query, SegmentedIndex};
+
+    const CITIES: &str = "\
+name,city,country\r
+Ada,London,UK\r
+\r
+\"Smith, Jo\",\"Paris\",France\r
+Lin,,\"The \"\"Big\"\" One\"\r
+";
+
+    fn layer<'a>(doc: &'a DelimitedDocument, name: &str) -> Vec<&'a str> {
+        doc.layers()
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|&(s, e)| &doc.text()[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn columns_are_named_by_the_header() {
+        let doc = DelimitedDocument::parse_csv(CITIES).unwrap();
+
+        assert_eq!(doc.columns(), ["name", "city", "country"]);
+        assert_eq!(layer(&doc, "#header"), ["name,city,country"]);
+        assert_eq!(layer(&doc, "name"), ["Ada", "\"Smith, Jo\"", "Lin"]);
+        assert_eq!(layer(&doc, "city"), ["London", "\"Paris\""]);
+        assert_eq!(
+            layer(&doc, "country"),
+            ["UK", "France", "\"The \"\"Big\"\" One\""]
+        );
+    }
+
+    #[test]
+    fn records_are_rows() {
+        let doc = DelimitedDocument::parse_csv(CITIES).unwrap();
+
+        assert_eq!(
+            layer(&doc, "#row"),
+            [
+                "Ada,London,UK",
+                "\"Smith, Jo\",\"Paris\",France",
+                "Lin,,\"The \"\"Big\"\" One\""
+            ]
+        );
+    }
+
+    #[test]
+    fn tab_separated_values_are_read() {
+        let doc = DelimitedDocument::parse_tsv("id\tcity\n1\tLondon\n2\tOslo").unwrap();
+
+        assert_eq!(layer(&doc, "city"), ["London", "Oslo"]);
+        assert_eq!(layer(&doc, "#row"), ["1\tLondon", "2\tOslo"]);
+    }
+
+    #[test]
+    fn quoted_fields_hold_line_breaks() {
+        let doc = DelimitedDocument::parse_csv("a,b\n\"one\ntwo\",3\n").unwrap();
+
+        assert_eq!(layer(&doc, "a"), ["\"one\ntwo\""]);
+        assert_eq!(layer(&doc, "#row").len(), 1);
+    }
+
+    #[test]
+    fn extra_fields_belong_to_no_column() {
+        let doc = DelimitedDocument::parse_csv("a,\nx,y,z\n").unwrap();
+
+        assert_eq!(doc.columns(), ["a", ""]);
+        assert_eq!(layer(&doc, "a"), ["x"]);
+        assert_eq!(doc.layers().len(), 3);
+    }
+
+    #[test]
+    fn invalid_quoting_is_rejected() {
+        assert_eq!(
+            DelimitedDocument::parse_csv("a\n\"open\n"),
+            Err(DelimitedError::UnclosedQuote { offset: 2 })
+        );
+        assert_eq!(
+            DelimitedDocument::parse_csv("a\n\"x\"y\n"),
+            Err(DelimitedError::Malformed { offset: 5 })
+        );
+    }
+
+    #[test]
+    fn reserved_column_names_are_rejected() {
+        assert_eq!(
+            DelimitedDocument::parse_csv(
+                "name,#row
+Bo,1
+"
+            ),
+            Err(DelimitedError::ReservedColumn {
+                name: "#row".to_string()
+            })
+        );
+        assert!(DelimitedDocument::parse_csv(
+            "#,name
+1,Bo
+"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn files_become_documents() {
+        let files = [CITIES, "name,city\nBo,Oslo\nEve,London\n"];
+        let mut index = SegmentedIndex::new();
+        let mut writer = index.writer();
+        for file in &files {
+            let doc = DelimitedDocument::parse_csv(*file).unwrap();
+            writer
+                .add_document(&doc.index(&AlphabeticTokenizer))
+                .unwrap();
+        }
+        writer.commit();
+
+        let in_city = Query::ContainedIn(
+            Box::new(Query::Term("london".into())),
+            Box::new(Query::Layer("city".into())),
+        );
+        let records = Query::Containing(
+            Box::new(Query::Layer("#row".into())),
+            Box::new(in_city.clone()),
+        );
+
+        assert_eq!(in_city.evaluate(&index).iter_tau().count(), 2);
+        let found: Vec<_> = records
+            .evaluate(&index)
+            .iter_tau()
+            .map(|(s, _)| s >> 32)
+            .collect();
+        assert_eq!(found, [0, 1]);
+    }
+}
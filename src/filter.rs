@@ -0,0 +1,314 @@
+use crate::{Tokenizer, ValidExtent};
+use rust_stemmers::{Algorithm, Stemmer};
+use std::{collections::HashSet, fmt, sync::Arc};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Transforms the terms produced by a [`Tokenizer`] before they are
+/// indexed.
+pub trait TokenFilter: fmt::Debug {
+    /// The term to index in place of `term`, or `None` to drop it.
+    fn filter(&self, term: String) -> Option<String>;
+}
+
+impl<F> TokenFilter for &F
+where
+    F: TokenFilter + ?Sized,
+{
+    fn filter(&self, term: String) -> Option<String> {
+        (**self).filter(term)
+    }
+}
+
+impl<F> TokenFilter for Box<F>
+where
+    F: TokenFilter + ?Sized,
+{
+    fn filter(&self, term: String) -> Option<String> {
+        (**self).filter(term)
+    }
+}
+
+/// Applies the Unicode default case folding, so that `STRASSE` and
+/// `straße` are the same term.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CaseFold;
+
+impl TokenFilter for CaseFold {
+    fn filter(&self, term: String) -> Option<String> {
+        Some(caseless::default_case_fold_str(&term))
+    }
+}
+
+/// Removes diacritics and replaces Latin letters that have no
+/// decomposition with their closest ASCII spelling, so that `café`
+/// and `cafe` are the same term.
+///
+/// Characters outside of the Latin script are left untouched.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AsciiFold;
+
+impl TokenFilter for AsciiFold {
+    fn filter(&self, term: String) -> Option<String> {
+        if term.is_ascii() {
+            return Some(term);
+        }
+
+        let mut folded = String::with_capacity(term.len());
+        for c in term.nfd().filter(|&c| !is_combining_mark(c)) {
+            match c {
+                'ß' => folded.push_str("ss"),
+                'æ' => folded.push_str("ae"),
+                'Æ' => folded.push_str("AE"),
+                'œ' => folded.push_str("oe"),
+                'Œ' => folded.push_str("OE"),
+                'þ' => folded.push_str("th"),
+                'Þ' => folded.push_str("TH"),
+                'ø' => folded.push('o'),
+                'Ø' => folded.push('O'),
+                'ł' => folded.push('l'),
+                'Ł' => folded.push('L'),
+                'đ' | 'ð' => folded.push('d'),
+                'Đ' | 'Ð' => folded.push('D'),
+                'ı' => folded.push('i'),
+                c => folded.push(c),
+            }
+        }
+        Some(folded)
+    }
+}
+
+/// Reduces English words to their stem using the Snowball (Porter 2)
+/// algorithm, so that `running` and `runs` are both indexed as `run`.
+///
+/// The stemmer expects lowercase terms.
+pub struct EnglishStemmer(Stemmer);
+
+impl EnglishStemmer {
+    pub fn new() -> Self {
+        EnglishStemmer(Stemmer::create(Algorithm::English))
+    }
+}
+
+impl Default for EnglishStemmer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for EnglishStemmer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnglishStemmer").finish()
+    }
+}
+
+impl TokenFilter for EnglishStemmer {
+    fn filter(&self, term: String) -> Option<String> {
+        Some(self.0.stem(&term).into_owned())
+    }
+}
+
+/// Drops terms that are too common to be worth indexing.
+///
+/// Terms are compared exactly, so this should follow any filter that
+/// changes their case.
+///
+/// A dropped term leaves no gap: it is not one of the tokens of the
+/// [`TOKEN_LAYER`](crate::TOKEN_LAYER), so the terms on either side of
+/// it are consecutive. A phrase such as `bank of america`, which is
+/// analyzed into `bank america`, therefore also finds `bank america`
+/// and `bank for america`.
+#[derive(Debug, Clone, Default)]
+pub struct StopWords {
+    words: HashSet<String>,
+}
+
+impl StopWords {
+    pub fn new<I>(words: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        StopWords {
+            words: words.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Common English function words.
+    pub fn english() -> Self {
+        Self::new(ENGLISH_STOP_WORDS.iter().copied())
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+}
+
+impl TokenFilter for StopWords {
+    fn filter(&self, term: String) -> Option<String> {
+        if self.contains(&term) {
+            None
+        } else {
+            Some(term)
+        }
+    }
+}
+
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// A tokenizer followed by a sequence of filters, applied in order.
+///
+/// A pipeline is itself a [`Tokenizer`]. Using the same pipeline to
+/// index documents and to [analyze query terms][crate::Query::analyze]
+/// ensures that both agree on the spelling of each term.
+#[derive(Debug, Clone)]
+pub struct Pipeline<T> {
+    tokenizer: T,
+    filters: Vec<Arc<dyn TokenFilter + Send + Sync>>,
+}
+
+impl<T> Pipeline<T>
+where
+    T: Tokenizer,
+{
+    pub fn new(tokenizer: T) -> Self {
+        Pipeline {
+            tokenizer,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Adds a filter to run after all existing filters.
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: TokenFilter + Send + Sync + 'static,
+    {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+}
+
+impl<T> Tokenizer for Pipeline<T>
+where
+    T: Tokenizer,
+{
+    fn tokenize<'t>(&self, text: &'t str) -> Box<dyn Iterator<Item = (String, ValidExtent)> + 't> {
+        let filters = self.filters.clone();
+
+        Box::new(
+            self.tokenizer
+                .tokenize(text)
+                .filter_map(move |(term, extent)| {
+                    let term = filters.iter().try_fold(term, |term, f| f.filter(term))?;
+                    Some((term, extent))
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, AlphabeticTokenizer, Query, UnicodeWordTokenizer};
+
+    fn terms<T: Tokenizer>(tokenizer: T, text: &str) -> Vec<String> {
+        tokenizer.tokenize(text).map(|(term, _)| term).collect()
+    }
+
+    fn filtered<F: TokenFilter>(filter: F, term: &str) -> Option<String> {
+        filter.filter(term.to_string())
+    }
+
+    #[test]
+    fn case_fold_goes_beyond_lowercase() {
+        assert_eq!(filtered(CaseFold, "STRASSE"), Some("strasse".into()));
+        assert_eq!(filtered(CaseFold, "Straße"), Some("strasse".into()));
+        assert_eq!(filtered(CaseFold, "ΣΊΣΥΦΟΣ"), Some("σίσυφοσ".into()));
+    }
+
+    #[test]
+    fn ascii_fold_removes_diacritics() {
+        assert_eq!(filtered(AsciiFold, "café"), Some("cafe".into()));
+        assert_eq!(filtered(AsciiFold, "Ångström"), Some("Angstrom".into()));
+        assert_eq!(filtered(AsciiFold, "łódź"), Some("lodz".into()));
+        assert_eq!(filtered(AsciiFold, "smørrebrød"), Some("smorrebrod".into()));
+        assert_eq!(filtered(AsciiFold, "東京"), Some("東京".into()));
+    }
+
+    #[test]
+    fn english_stemmer_conflates_inflections() {
+        let stemmer = EnglishStemmer::new();
+
+        for word in &["run", "running", "runs"] {
+            assert_eq!(filtered(&stemmer, word), Some("run".into()));
+        }
+        assert_eq!(filtered(&stemmer, "connections"), Some("connect".into()));
+    }
+
+    #[test]
+    fn stop_words_are_dropped() {
+        let pipeline = Pipeline::new(AlphabeticTokenizer).with_filter(StopWords::english());
+
+        assert_eq!(terms(&pipeline, "The cat and the hat"), ["cat", "hat"]);
+    }
+
+    #[test]
+    fn phrases_match_across_stop_words() {
+        let pipeline = Pipeline::new(AlphabeticTokenizer)
+            .with_filter(CaseFold)
+            .with_filter(StopWords::english());
+        let text = "Bank of America, bank America, bank for America";
+        let index = pipeline.index(text);
+
+        let query = Query::Term("Bank of America".into()).analyze(&pipeline);
+        let found: Vec<_> = query
+            .evaluate(&index)
+            .iter_tau()
+            .map(|(s, e)| &text[s as usize..e as usize])
+            .collect();
+
+        assert_eq!(
+            found,
+            ["Bank of America", "bank America", "bank for America"]
+        );
+    }
+
+    #[test]
+    fn custom_stop_words_are_dropped() {
+        let pipeline = Pipeline::new(AlphabeticTokenizer).with_filter(StopWords::new(vec!["cat"]));
+
+        assert_eq!(
+            terms(&pipeline, "The cat and the hat"),
+            ["the", "and", "the", "hat"]
+        );
+    }
+
+    #[test]
+    fn pipeline_applies_filters_in_order_and_keeps_extents() {
+        let pipeline = Pipeline::new(UnicodeWordTokenizer)
+            .with_filter(CaseFold)
+            .with_filter(AsciiFold)
+            .with_filter(StopWords::english())
+            .with_filter(EnglishStemmer::new());
+
+        let found: Vec<_> = pipeline.tokenize("The CAFÉS are running").collect();
+
+        assert_eq!(
+            found,
+            [("cafe".to_string(), (4, 10)), ("run".to_string(), (15, 22))]
+        );
+    }
+
+    #[test]
+    fn pipeline_without_filters_matches_tokenizer() {
+        let text = "Hello, world! Goodbye, world!";
+
+        assert_eq!(
+            terms(Pipeline::new(AlphabeticTokenizer), text),
+            terms(AlphabeticTokenizer, text)
+        );
+    }
+}
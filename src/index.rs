@@ -0,0 +1,79 @@
+use crate::ValidExtent;
+use std::{collections::HashMap, sync::Arc};
+
+/// The posting lists for the terms and layers of a collection of
+/// documents.
+///
+/// Each posting list is expected to be a GC-list: ordered by position
+/// with no extent nested inside another.
+///
+/// The posting lists are reference counted so that queries may hold
+/// on to them independently of the index, such as when evaluating
+/// queries on other threads.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Index {
+    terms: HashMap<String, Arc<Vec<ValidExtent>>>,
+    layers: HashMap<String, Arc<Vec<ValidExtent>>>,
+}
+
+impl Index {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The extents of the term, empty if it does not occur.
+    pub fn term(&self, term: &str) -> &[ValidExtent] {
+        self.terms.get(term).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+
+    /// The extents of the layer, empty if it does not exist.
+    pub fn layer(&self, name: &str) -> &[ValidExtent] {
+        self.layers.get(name).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+
+    /// Whether the layer has been added, even if it has no extents.
+    pub fn has_layer(&self, name: &str) -> bool {
+        self.layers.contains_key(name)
+    }
+
+    /// A shared handle to the extents of the term.
+    pub fn shared_term(&self, term: &str) -> Arc<Vec<ValidExtent>> {
+        self.terms.get(term).cloned().unwrap_or_default()
+    }
+
+    /// A shared handle to the extents of the layer.
+    pub fn shared_layer(&self, name: &str) -> Arc<Vec<ValidExtent>> {
+        self.layers.get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn terms(&self) -> impl Iterator<Item = (&str, &[ValidExtent])> {
+        self.terms.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    pub fn layers(&self) -> impl Iterator<Item = (&str, &[ValidExtent])> {
+        self.layers.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    /// Appends extents to the term's posting list. They must come
+    /// after any extents already present.
+    ///
+    /// If the posting list is shared by a query, it is copied first.
+    pub fn extend_term<I>(&mut self, term: impl Into<String>, extents: I)
+    where
+        I: IntoIterator<Item = ValidExtent>,
+    {
+        Arc::make_mut(self.terms.entry(term.into()).or_default()).extend(extents);
+    }
+
+    /// Appends extents to the layer. They must come after any extents
+    /// already present.
+    ///
+    /// If the layer is shared by a query, it is copied first.
+    pub fn extend_layer<I>(&mut self, name: impl Into<String>, extents: I)
+    where
+        I: IntoIterator<Item = ValidExtent>,
+    {
+        Arc::make_mut(self.layers.entry(name.into()).or_default()).extend(extents);
+    }
+}
@@ -0,0 +1,557 @@
+use crate::{Index, NestedLayer, Tokenizer, ValidExtent};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// A reason that a JSON document could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    /// A character that cannot appear at this point of a value.
+    Malformed { offset: usize },
+    /// A backslash escape in a string is not valid, or a `\u` escape
+    /// is an unpaired surrogate.
+    InvalidEscape { offset: usize },
+    /// Arrays and objects are nested more deeply than the limit of
+    /// 128 levels.
+    TooDeep { offset: usize },
+    /// The document ended inside a value.
+    UnexpectedEnd,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Malformed { offset } => write!(f, "malformed JSON at byte {}", offset),
+            JsonError::InvalidEscape { offset } => {
+                write!(f, "invalid escape sequence at byte {}", offset)
+            }
+            JsonError::TooDeep { offset } => {
+                write!(f, "values are nested too deeply at byte {}", offset)
+            }
+            JsonError::UnexpectedEnd => "the document ended inside a value".fmt(f),
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+const MAX_DEPTH: usize = 128;
+
+/// A JSON document, with layers for its structure.
+///
+/// The text is the JSON source itself, so the layers are byte offsets
+/// into the source with an exclusive end. Each value is covered by
+/// the layer of its key path, such as `user.name`, where the elements
+/// of an array add `[]` to the path of the array, as in
+/// `users[].name`. The outermost value has no key path.
+///
+/// Each value is also covered by the layer of its type: `#object`,
+/// `#array`, `#string`, `#number`, `#boolean` or `#null`.
+///
+/// A document may hold several values separated by whitespace, as
+/// JSON Lines logs do. They are all addressed by the same key paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonDocument {
+    text: String,
+    layers: HashMap<String, Vec<ValidExtent>>,
+    // The contents of each string value, without the quotes
+    strings: Vec<(usize, usize)>,
+}
+
+impl JsonDocument {
+    /// Reads a document of zero or more JSON values.
+    pub fn parse(source: impl Into<String>) -> Result<Self, JsonError> {
+        let text = source.into();
+        let (layers, strings) = {
+            let mut parser = Parser::new(&text);
+            parser.run()?;
+            (parser.layers, parser.strings)
+        };
+
+        Ok(JsonDocument {
+            text,
+            layers,
+            strings,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// One layer for each key path and each type. Key path layers are
+    /// always GC-lists. Where values of the same type are nested, only
+    /// the outermost is kept, so that a word inside any object is
+    /// inside the `#object` layer; [`nested_layers`](Self::nested_layers)
+    /// keeps all of them.
+    pub fn layers(&self) -> HashMap<String, Vec<ValidExtent>> {
+        self.nested_layers()
+            .into_iter()
+            .map(|(name, nested)| (name, nested.outermost().to_vec()))
+            .collect()
+    }
+
+    /// The same layers as [`layers`](Self::layers), keeping every
+    /// value.
+    pub fn nested_layers(&self) -> HashMap<String, NestedLayer> {
+        self.layers
+            .iter()
+            .map(|(name, extents)| (name.clone(), NestedLayer::new(extents.iter().copied())))
+            .collect()
+    }
+
+    /// Indexes the terms of every string value along with every layer.
+    ///
+    /// Escape sequences are decoded before the contents are tokenized,
+    /// and the extents of the terms cover the escape sequences in the
+    /// source. Keys, numbers and literals are not indexed.
+    pub fn index<T>(&self, tokenizer: &T) -> Index
+    where
+        T: Tokenizer + ?Sized,
+    {
+        let mut index = Index::new();
+        for &(start, end) in &self.strings {
+            let (decoded, checkpoints) =
+                unescape(&self.text[start..end]).expect("Strings are checked when parsing");
+
+            // Offsets in the decoded string that follow an escape
+            // sequence are moved by its length
+            let to_source = |offset: usize| {
+                let i = checkpoints.partition_point(|&(decoded, _)| decoded <= offset);
+                let (decoded, raw) = checkpoints[i - 1];
+                (start + raw + offset - decoded) as u64
+            };
+
+            for (term, (s, e)) in tokenizer.tokenize(&decoded) {
+                let extent = (to_source(s as usize), to_source(e as usize));
+                index.extend_term(term, Some(extent));
+            }
+        }
+        for (name, extents) in self.layers() {
+            index.extend_layer(name, extents);
+        }
+        index
+    }
+}
+
+// Decodes the contents of a string, along with the offset in the
+// decoded and raw strings after each escape sequence, starting with
+// (0, 0). An error is the offset of the invalid escape sequence.
+fn unescape(raw: &str) -> Result<(String, Vec<(usize, usize)>), usize> {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut checkpoints = vec![(0, 0)];
+    let mut rest = raw;
+
+    while let Some(i) = rest.find('\\') {
+        decoded.push_str(&rest[..i]);
+        let escape = raw.len() - rest.len() + i;
+        let after = &rest[i + 1..];
+
+        let (c, len) = match after.as_bytes().first() {
+            Some(b'"') => ('"', 1),
+            Some(b'\\') => ('\\', 1),
+            Some(b'/') => ('/', 1),
+            Some(b'b') => ('\u{8}', 1),
+            Some(b'f') => ('\u{c}', 1),
+            Some(b'n') => ('\n', 1),
+            Some(b'r') => ('\r', 1),
+            Some(b't') => ('\t', 1),
+            Some(b'u') => {
+                let high = hex4(&after[1..]).ok_or(escape)?;
+                match high {
+                    0xD800..=0xDBFF => {
+                        let low = after[5..]
+                            .strip_prefix("\\u")
+                            .and_then(hex4)
+                            .filter(|low| (0xDC00..=0xDFFF).contains(low))
+                            .ok_or(escape)?;
+                        let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                        (char::from_u32(c).ok_or(escape)?, 11)
+                    }
+                    _ => (char::from_u32(high).ok_or(escape)?, 5),
+                }
+            }
+            _ => return Err(escape),
+        };
+
+        decoded.push(c);
+        rest = &after[len..];
+        checkpoints.push((decoded.len(), raw.len() - rest.len()));
+    }
+    decoded.push_str(rest);
+
+    Ok((decoded, checkpoints))
+}
+
+fn hex4(s: &str) -> Option<u32> {
+    let digits = s.get(..4)?;
+    if digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        u32::from_str_radix(digits, 16).ok()
+    } else {
+        None
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    path: String,
+    layers: HashMap<String, Vec<ValidExtent>>,
+    strings: Vec<(usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Parser {
+            source,
+            pos: 0,
+            path: String::new(),
+            layers: HashMap::new(),
+            strings: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), JsonError> {
+        loop {
+            self.skip_whitespace();
+            if self.pos == self.source.len() {
+                return Ok(());
+            }
+            self.value(0)?;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(b) if b == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(JsonError::Malformed { offset: self.pos }),
+            None => Err(JsonError::UnexpectedEnd),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<(), JsonError> {
+        let start = self.pos;
+        let kind = match self.peek() {
+            Some(b'{') => {
+                self.object(depth)?;
+                "#object"
+            }
+            Some(b'[') => {
+                self.array(depth)?;
+                "#array"
+            }
+            Some(b'"') => {
+                let contents = self.string()?;
+                self.strings.push(contents);
+                "#string"
+            }
+            Some(b't') => {
+                self.literal("true")?;
+                "#boolean"
+            }
+            Some(b'f') => {
+                self.literal("false")?;
+                "#boolean"
+            }
+            Some(b'n') => {
+                self.literal("null")?;
+                "#null"
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                self.number()?;
+                "#number"
+            }
+            Some(_) => return Err(JsonError::Malformed { offset: self.pos }),
+            None => return Err(JsonError::UnexpectedEnd),
+        };
+
+        let extent = (start as u64, self.pos as u64);
+        self.layers
+            .entry(kind.to_string())
+            .or_default()
+            .push(extent);
+        if !self.path.is_empty() {
+            self.layers
+                .entry(self.path.clone())
+                .or_default()
+                .push(extent);
+        }
+        Ok(())
+    }
+
+    fn object(&mut self, depth: usize) -> Result<(), JsonError> {
+        if depth == MAX_DEPTH {
+            return Err(JsonError::TooDeep { offset: self.pos });
+        }
+        self.expect(b'{')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        loop {
+            self.skip_whitespace();
+            let (start, end) = self.string()?;
+            let (key, _) =
+                unescape(&self.source[start..end]).expect("Strings are checked when parsing");
+            self.skip_whitespace();
+            self.expect(b':')?;
+            self.skip_whitespace();
+
+            let parent = self.path.len();
+            if !self.path.is_empty() {
+                self.path.push('.');
+            }
+            self.path.push_str(&key);
+            self.value(depth + 1)?;
+            self.path.truncate(parent);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some(_) => return Err(JsonError::Malformed { offset: self.pos }),
+                None => return Err(JsonError::UnexpectedEnd),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<(), JsonError> {
+        if depth == MAX_DEPTH {
+            return Err(JsonError::TooDeep { offset: self.pos });
+        }
+        self.expect(b'[')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(());
+        }
+
+        let parent = self.path.len();
+        self.path.push_str("[]");
+        loop {
+            self.skip_whitespace();
+            self.value(depth + 1)?;
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    self.path.truncate(parent);
+                    return Ok(());
+                }
+                Some(_) => return Err(JsonError::Malformed { offset: self.pos }),
+                None => return Err(JsonError::UnexpectedEnd),
+            }
+        }
+    }
+
+    // The extent of the contents, with every escape sequence checked
+    fn string(&mut self) -> Result<(usize, usize), JsonError> {
+        self.expect(b'"')?;
+        let start = self.pos;
+
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => self.pos += 2,
+                Some(0x00..=0x1F) => return Err(JsonError::Malformed { offset: self.pos }),
+                Some(_) => self.pos += 1,
+                None => return Err(JsonError::UnexpectedEnd),
+            }
+        }
+        let end = self.pos;
+        self.pos += 1;
+
+        unescape(&self.source[start..end]).map_err(|offset| JsonError::InvalidEscape {
+            offset: start + offset,
+        })?;
+        Ok((start, end))
+    }
+
+    fn literal(&mut self, literal: &str) -> Result<(), JsonError> {
+        let rest = &self.source[self.pos..];
+        if rest.starts_with(literal) {
+            self.pos += literal.len();
+            Ok(())
+        } else if literal.starts_with(rest) {
+            Err(JsonError::UnexpectedEnd)
+        } else {
+            Err(JsonError::Malformed { offset: self.pos })
+        }
+    }
+
+    fn number(&mut self) -> Result<(), JsonError> {
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            Some(_) => return Err(JsonError::Malformed { offset: self.pos }),
+            None => return Err(JsonError::UnexpectedEnd),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.required_digits()?;
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            self.required_digits()?;
+        }
+        Ok(())
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn required_digits(&mut self) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(b'0'..=b'9') => {
+                self.digits();
+                Ok(())
+            }
+            Some(_) => Err(JsonError::Malformed { offset: self.pos }),
+            None => Err(JsonError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, AlphabeticTokenizer, Query};
+
+    const LOG: &str = r#"{"level": "error", "user": {"name": "Ada", "id": 7},
+ "error": {"message": "Connection timeout after 30s", "retry": true},
+ "tags": ["net", "db"], "extra": null}
+{"level": "info", "error": {"message": "No timeout"}, "tags": []}
+"#;
+
+    fn layer<'a>(doc: &'a JsonDocument, name: &str) -> Vec<&'a str> {
+        doc.layers()
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|&(s, e)| &doc.text()[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn values_are_covered_by_their_key_paths() {
+        let doc = JsonDocument::parse(LOG).unwrap();
+
+        assert_eq!(doc.text(), LOG);
+        assert_eq!(layer(&doc, "user.name"), [r#""Ada""#]);
+        assert_eq!(layer(&doc, "user.id"), ["7"]);
+        assert_eq!(
+            layer(&doc, "error.message"),
+            [r#""Connection timeout after 30s""#, r#""No timeout""#]
+        );
+        assert_eq!(layer(&doc, "tags"), [r#"["net", "db"]"#, "[]"]);
+        assert_eq!(layer(&doc, "tags[]"), [r#""net""#, r#""db""#]);
+    }
+
+    #[test]
+    fn values_are_covered_by_their_types() {
+        let doc = JsonDocument::parse(LOG).unwrap();
+
+        assert_eq!(layer(&doc, "#boolean"), ["true"]);
+        assert_eq!(layer(&doc, "#null"), ["null"]);
+        assert_eq!(layer(&doc, "#number"), ["7"]);
+        assert_eq!(layer(&doc, "#array"), [r#"["net", "db"]"#, "[]"]);
+        assert_eq!(doc.nested_layers()["#object"].outermost().len(), 2);
+    }
+
+    #[test]
+    fn terms_come_from_string_values() {
+        let doc = JsonDocument::parse(LOG).unwrap();
+        let index = doc.index(&AlphabeticTokenizer);
+
+        let query = Query::ContainedIn(
+            Box::new(Query::Term("timeout".into())),
+            Box::new(Query::Layer("error.message".into())),
+        );
+        let found: Vec<_> = query.evaluate(&index).iter_tau().collect();
+
+        assert_eq!(found.len(), 2);
+        assert!(index.term("message").is_empty());
+        assert!(index.term("null").is_empty());
+    }
+
+    #[test]
+    fn escaped_terms_cover_the_source() {
+        let source = r#"{"a": "caf\u00e9 \"quoted\"\nnext \ud83d\ude00 end"}"#;
+        let doc = JsonDocument::parse(source).unwrap();
+        let index = doc.index(&AlphabeticTokenizer);
+
+        let text = |term: &str| -> Vec<&str> {
+            index
+                .term(term)
+                .iter()
+                .map(|&(s, e)| &source[s as usize..e as usize])
+                .collect()
+        };
+        assert_eq!(text("café"), [r"caf\u00e9"]);
+        assert_eq!(text("quoted"), ["quoted"]);
+        assert_eq!(text("next"), ["next"]);
+        assert_eq!(text("end"), ["end"]);
+    }
+
+    #[test]
+    fn escaped_keys_are_decoded() {
+        let doc = JsonDocument::parse(r#"{"ab": {"c\"": 1}}"#).unwrap();
+
+        assert_eq!(layer(&doc, "ab.c\""), ["1"]);
+    }
+
+    #[test]
+    fn invalid_documents_are_rejected() {
+        let parse = |s: &str| JsonDocument::parse(s).unwrap_err();
+
+        assert_eq!(parse(r#"{"a" 1}"#), JsonError::Malformed { offset: 5 });
+        assert_eq!(parse(r#"{"a": [1, 2"#), JsonError::UnexpectedEnd);
+        assert_eq!(parse(r#"["\x"]"#), JsonError::InvalidEscape { offset: 2 });
+        assert_eq!(
+            parse(r#"["\ud83d"]"#),
+            JsonError::InvalidEscape { offset: 2 }
+        );
+        assert_eq!(parse("[01]"), JsonError::Malformed { offset: 2 });
+        assert_eq!(parse("[1.]"), JsonError::Malformed { offset: 3 });
+        assert_eq!(parse("tru"), JsonError::UnexpectedEnd);
+        assert_eq!(parse("[1,]"), JsonError::Malformed { offset: 3 });
+        assert_eq!(
+            parse(&"[".repeat(200)),
+            JsonError::TooDeep { offset: MAX_DEPTH }
+        );
+    }
+
+    #[test]
+    fn empty_documents_have_no_layers() {
+        let doc = JsonDocument::parse(" \n").unwrap();
+
+        assert!(doc.layers().is_empty());
+    }
+}
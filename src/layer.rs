@@ -0,0 +1,288 @@
+use crate::ValidExtent;
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Finds the structural regions of a text, such as its sentences, to
+/// be added to an index as a layer.
+///
+/// The extents are byte offsets into the text, with an exclusive end,
+/// and form a GC-list. Leading and trailing whitespace is never part
+/// of a region, and regions containing only whitespace are omitted.
+pub trait LayerExtractor {
+    fn extract(&self, text: &str) -> Vec<ValidExtent>;
+}
+
+impl<T> LayerExtractor for &T
+where
+    T: LayerExtractor + ?Sized,
+{
+    fn extract(&self, text: &str) -> Vec<ValidExtent> {
+        (**self).extract(text)
+    }
+}
+
+impl<T> LayerExtractor for Box<T>
+where
+    T: LayerExtractor + ?Sized,
+{
+    fn extract(&self, text: &str) -> Vec<ValidExtent> {
+        (**self).extract(text)
+    }
+}
+
+/// Each line of the text, without its line terminator.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LineLayer;
+
+impl LayerExtractor for LineLayer {
+    fn extract(&self, text: &str) -> Vec<ValidExtent> {
+        lines(text)
+            .filter_map(|(start, line)| trimmed(text, start, start + line.len()))
+            .collect()
+    }
+}
+
+/// Each run of lines separated from the others by blank lines.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ParagraphLayer;
+
+impl LayerExtractor for ParagraphLayer {
+    fn extract(&self, text: &str) -> Vec<ValidExtent> {
+        let mut extents = Vec::new();
+        let mut paragraph: Option<(usize, usize)> = None;
+
+        for (start, line) in lines(text) {
+            if line.trim().is_empty() {
+                if let Some((start, end)) = paragraph.take() {
+                    extents.extend(trimmed(text, start, end));
+                }
+            } else {
+                let end = start + line.len();
+                let (start, _) = paragraph.unwrap_or((start, end));
+                paragraph = Some((start, end));
+            }
+        }
+        if let Some((start, end)) = paragraph {
+            extents.extend(trimmed(text, start, end));
+        }
+
+        extents
+    }
+}
+
+/// Each sentence of the text, found with the Unicode sentence
+/// boundary rules of [UAX #29][].
+///
+/// Those rules end a sentence after any period followed by a capital
+/// letter, so a period ending a known abbreviation or a single-letter
+/// initial, as in `Dr. J. Smith`, does not end the sentence. A line
+/// break after the period always does.
+///
+/// [UAX #29]: https://www.unicode.org/reports/tr29/
+#[derive(Debug, Clone)]
+pub struct SentenceLayer {
+    // Lowercase, without the final period
+    abbreviations: HashSet<String>,
+}
+
+impl SentenceLayer {
+    /// Recognizes common English abbreviations.
+    pub fn new() -> Self {
+        Self::with_abbreviations(ENGLISH_ABBREVIATIONS.iter().copied())
+    }
+
+    /// Recognizes the given abbreviations, which are compared without
+    /// regard to case and may be written with or without their final
+    /// period.
+    pub fn with_abbreviations<I>(abbreviations: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let abbreviations = abbreviations
+            .into_iter()
+            .map(|a| a.as_ref().trim_end_matches('.').to_lowercase())
+            .collect();
+        SentenceLayer { abbreviations }
+    }
+
+    // Whether the final period of the piece belongs to an
+    // abbreviation, and the piece does not end with a line break.
+    fn continues(&self, piece: &str) -> bool {
+        let content = piece.trim_end();
+        if piece[content.len()..].contains(['\n', '\r']) {
+            return false;
+        }
+
+        let word = match content.strip_suffix('.') {
+            Some(before) => before.split_whitespace().last().unwrap_or(""),
+            None => return false,
+        };
+        let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+
+        let mut chars = word.chars();
+        let is_initial =
+            matches!((chars.next(), chars.next()), (Some(c), None) if c.is_uppercase());
+
+        is_initial || self.abbreviations.contains(&word.to_lowercase())
+    }
+}
+
+impl Default for SentenceLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayerExtractor for SentenceLayer {
+    fn extract(&self, text: &str) -> Vec<ValidExtent> {
+        let mut extents = Vec::new();
+        let mut sentence_start = None;
+
+        for (offset, piece) in text.split_sentence_bound_indices() {
+            let start = *sentence_start.get_or_insert(offset);
+            if self.continues(piece) {
+                continue;
+            }
+            sentence_start = None;
+            extents.extend(trimmed(text, start, offset + piece.len()));
+        }
+        if let Some(start) = sentence_start {
+            extents.extend(trimmed(text, start, text.len()));
+        }
+
+        extents
+    }
+}
+
+const ENGLISH_ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "rev", "gen", "col", "capt", "lt",
+    "sgt", "gov", "sen", "rep", "vs", "e.g", "i.e", "cf", "approx", "fig", "figs", "vol", "eq",
+    "ch", "sec", "dept", "inc", "ltd", "corp", "co", "jan", "feb", "mar", "apr", "jun", "jul",
+    "aug", "sep", "sept", "oct", "nov", "dec",
+];
+
+// Each line with its offset, without the line terminator
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        Some((start, line))
+    })
+}
+
+// The extent of text[start..end] without surrounding whitespace
+pub(crate) fn trimmed(text: &str, start: usize, end: usize) -> Option<ValidExtent> {
+    let region = &text[start..end];
+    let leading = region.len() - region.trim_start().len();
+    let content = region.trim();
+    if content.is_empty() {
+        None
+    } else {
+        let start = start + leading;
+        Some((start as u64, (start + content.len()) as u64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    fn regions<L: LayerExtractor>(layer: L, text: &str) -> Vec<&str> {
+        layer
+            .extract(text)
+            .into_iter()
+            .map(|(s, e)| &text[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn sentences_of_the_readme_example() {
+        let text = "Hello, world! Goodbye, world!";
+
+        assert_eq!(SentenceLayer::new().extract(text), [(0, 13), (14, 29)]);
+        assert_eq!(ParagraphLayer.extract(text), [(0, 29)]);
+    }
+
+    #[test]
+    fn abbreviations_do_not_end_sentences() {
+        let text = "Dr. J. Smith arrived, e.g. on time. He met Mrs. Jones. Then it rained.";
+
+        assert_eq!(
+            regions(SentenceLayer::new(), text),
+            [
+                "Dr. J. Smith arrived, e.g. on time.",
+                "He met Mrs. Jones.",
+                "Then it rained."
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_abbreviations_do_not_end_sentences() {
+        let text = "See Abb. Two for details. Done.";
+
+        assert_eq!(
+            regions(SentenceLayer::with_abbreviations(vec!["Abb."]), text),
+            ["See Abb. Two for details.", "Done."]
+        );
+        assert_eq!(
+            regions(SentenceLayer::new(), text),
+            ["See Abb.", "Two for details.", "Done."]
+        );
+    }
+
+    #[test]
+    fn line_breaks_end_sentences_after_abbreviations() {
+        let text = "Signed by the Dr.\n\nNext paragraph.";
+
+        assert_eq!(
+            regions(SentenceLayer::new(), text),
+            ["Signed by the Dr.", "Next paragraph."]
+        );
+    }
+
+    #[test]
+    fn paragraphs_are_separated_by_blank_lines() {
+        let text = "\n  First line\r\nstill first.\r\n \t\r\nSecond.\n\n\n\nThird\n";
+
+        assert_eq!(
+            regions(ParagraphLayer, text),
+            ["First line\r\nstill first.", "Second.", "Third"]
+        );
+    }
+
+    #[test]
+    fn lines_exclude_terminators_and_blank_lines() {
+        let text = "one\r\n\n  two  \n   \nthree";
+
+        assert_eq!(regions(LineLayer, text), ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn every_layer_is_a_gc_list_of_trimmed_regions() {
+        fn prop(raw: Vec<u8>) -> bool {
+            let alphabet = ['a', 'B', ' ', '.', '\n', '\r', 'é', '?'];
+            let text: String = raw.iter().map(|b| alphabet[*b as usize % 8]).collect();
+            let layers: [&dyn LayerExtractor; 3] =
+                [&LineLayer, &ParagraphLayer, &SentenceLayer::new()];
+
+            layers.iter().all(|layer| {
+                let extents = layer.extract(&text);
+                let ordered = extents
+                    .windows(2)
+                    .all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1);
+                let trimmed = extents.iter().all(|&(s, e)| {
+                    let region = &text[s as usize..e as usize];
+                    !region.is_empty() && region.trim() == region
+                });
+                ordered && trimmed
+            })
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+}
@@ -1,11 +1,80 @@
+//! Queries over the regions of text, such as words, sentences and
+//! elements, combined with the operators of a region algebra.
+//!
+//! # Features
+//!
+//! The `serde` feature implements `Serialize` and `Deserialize` for
+//! positions, extents, extent lists, indexes and queries. It also
+//! brings in the JSON readers: `Query::from_json`, which reads the
+//! JSON form of a query and reports a `QueryError`, and
+//! `Annotations::from_web_annotation`. Without the feature, queries
+//! are built from the variants of [`Query`].
+
 #![deny(rust_2018_idioms)]
 
 use crate::Position::*;
 use std::{
     cmp::{max, min},
-    u32, u64,
+    sync::Arc,
+};
+
+mod annotation;
+mod code;
+mod compact;
+mod context;
+mod deletion;
+mod delimited;
+mod filter;
+mod index;
+mod json;
+mod layer;
+mod markdown;
+mod markup;
+mod materialize;
+mod merged;
+mod nested;
+mod ngram;
+mod offsets;
+mod pattern;
+mod phrase;
+mod query;
+mod segment;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shard;
+mod tokenize;
+
+pub use crate::{
+    annotation::{AnnotationError, Annotations},
+    code::{CodeDocument, CodeSyntax},
+    compact::{CompactError, CompactExtents},
+    context::{EvaluationContext, Interrupted, Interruptible, Limited},
+    deletion::{Deletions, SkipDeleted},
+    delimited::{DelimitedDocument, DelimitedError},
+    filter::{AsciiFold, CaseFold, EnglishStemmer, Pipeline, StopWords, TokenFilter},
+    index::Index,
+    json::{JsonDocument, JsonError},
+    layer::{LayerExtractor, LineLayer, ParagraphLayer, SentenceLayer},
+    markdown::MarkdownDocument,
+    markup::{Element, MarkupDocument, MarkupError},
+    materialize::{Materialization, Materialized, QueryCache},
+    merged::MergedLists,
+    nested::NestedLayer,
+    ngram::NgramIndex,
+    offsets::{OffsetError, OffsetMap, OffsetUnit},
+    pattern::{RegexError, RegexSearch},
+    phrase::Phrase,
+    query::{Query, Source},
+    segment::{
+        CompletedMerge, IndexWriter, Merge, MergeError, Segment, SegmentedIndex, WriteError,
+    },
+    shard::{Shard, ShardError, ShardedIndex},
+    tokenize::{AlphabeticTokenizer, TokenOffsets, Tokenizer, UnicodeWordTokenizer, TOKEN_LAYER},
 };
 
+#[cfg(feature = "serde")]
+pub use crate::query::QueryError;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Position {
     NegativeInfinity,
@@ -45,6 +114,7 @@ impl Epsilon for Position {
 pub type ValidExtent = (u64, u64);
 
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Extent(pub Position, pub Position);
 const START_EXTENT: Extent = Extent(NegativeInfinity, NegativeInfinity);
 const END_EXTENT: Extent = Extent(PositiveInfinity, PositiveInfinity);
@@ -168,7 +238,25 @@ pub trait Algebra {
     }
 }
 
-impl<'a, A: ?Sized> Algebra for Box<A>
+impl<A: ?Sized> Algebra for Box<A>
+where
+    A: Algebra,
+{
+    fn tau(&self, k: Position) -> Extent {
+        (**self).tau(k)
+    }
+    fn tau_prime(&self, k: Position) -> Extent {
+        (**self).tau_prime(k)
+    }
+    fn rho(&self, k: Position) -> Extent {
+        (**self).rho(k)
+    }
+    fn rho_prime(&self, k: Position) -> Extent {
+        (**self).rho_prime(k)
+    }
+}
+
+impl<A: ?Sized> Algebra for &A
 where
     A: Algebra,
 {
@@ -186,7 +274,7 @@ where
     }
 }
 
-impl<'a, A: ?Sized> Algebra for &'a A
+impl<A: ?Sized> Algebra for Arc<A>
 where
     A: Algebra,
 {
@@ -385,6 +473,21 @@ impl Algebra for [ValidExtent] {
     }
 }
 
+impl Algebra for Vec<ValidExtent> {
+    fn tau(&self, k: Position) -> Extent {
+        self[..].tau(k)
+    }
+    fn tau_prime(&self, k: Position) -> Extent {
+        self[..].tau_prime(k)
+    }
+    fn rho(&self, k: Position) -> Extent {
+        self[..].rho(k)
+    }
+    fn rho_prime(&self, k: Position) -> Extent {
+        self[..].rho_prime(k)
+    }
+}
+
 /// Finds no extents
 pub struct Empty;
 
@@ -417,6 +520,7 @@ fn doc_and_offset_to_k(doc: u32, offset: u32) -> u64 {
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Documents {
     count: u32,
 }
@@ -472,17 +576,15 @@ impl Algebra for Documents {
     fn rho(&self, k: Position) -> Extent {
         let k = check_and_unwrap_forwards!(k);
 
-        match k_to_doc_and_offset(k) {
-            (doc, _) => self.doc_index_to_extent_forwards(doc),
-        }
+        let (doc, _) = k_to_doc_and_offset(k);
+        self.doc_index_to_extent_forwards(doc)
     }
 
     fn rho_prime(&self, k: Position) -> Extent {
         let k = check_and_unwrap_backwards!(k);
 
-        match k_to_doc_and_offset(k) {
-            (doc, _) => self.doc_index_to_extent_backwards(doc),
-        }
+        let (doc, _) = k_to_doc_and_offset(k);
+        self.doc_index_to_extent_backwards(doc)
     }
 }
 
@@ -670,28 +772,44 @@ where
     B: Algebra,
 {
     fn tau(&self, k: Position) -> Extent {
-        check_forwards!(k);
-        let Extent(p0, q0) = self.a.tau(k);
-        let Extent(p1, q1) = self.b.rho(q0);
+        let mut k = k;
 
-        if p1 > p0 {
-            Extent(p0, q0)
-        } else {
-            // TODO: prevent recursion?
-            self.rho(q1.increment())
+        loop {
+            check_forwards!(k);
+
+            let Extent(p0, q0) = self.a.tau(k);
+            let Extent(p1, q1) = self.b.rho(q0);
+
+            if p1 > p0 {
+                return Extent(p0, q0);
+            }
+
+            // iteration instead of recursion; this is `self.rho(q1 + 1)`
+            k = q1.increment();
+            check_forwards!(k);
+            let Extent(p, _) = self.a.rho(k);
+            k = p;
         }
     }
 
     fn tau_prime(&self, k: Position) -> Extent {
-        check_backwards!(k);
-        let Extent(p0, q0) = self.a.tau_prime(k);
-        let Extent(p1, q1) = self.b.rho_prime(p0);
+        let mut k = k;
 
-        if q1 < q0 {
-            Extent(p0, q0)
-        } else {
-            // TODO: prevent recursion?
-            self.rho_prime(p1.decrement())
+        loop {
+            check_backwards!(k);
+
+            let Extent(p0, q0) = self.a.tau_prime(k);
+            let Extent(p1, q1) = self.b.rho_prime(p0);
+
+            if q1 < q0 {
+                return Extent(p0, q0);
+            }
+
+            // iteration instead of recursion; this is `self.rho_prime(p1 - 1)`
+            k = p1.decrement();
+            check_backwards!(k);
+            let Extent(_, q) = self.a.rho_prime(k);
+            k = q;
         }
     }
 
@@ -750,30 +868,44 @@ where
     }
 
     fn rho(&self, k: Position) -> Extent {
-        check_forwards!(k);
+        let mut k = k;
 
-        let Extent(p0, q0) = self.a.rho(k);
-        let Extent(p1, q1) = self.b.tau(p0);
+        loop {
+            check_forwards!(k);
 
-        if q1 > q0 {
-            Extent(p0, q0)
-        } else {
-            // TODO: prevent recursion?
-            self.tau(p1.increment())
+            let Extent(p0, q0) = self.a.rho(k);
+            let Extent(p1, q1) = self.b.tau(p0);
+
+            if q1 > q0 {
+                return Extent(p0, q0);
+            }
+
+            // iteration instead of recursion; this is `self.tau(p1 + 1)`
+            k = p1.increment();
+            check_forwards!(k);
+            let Extent(_, q) = self.a.tau(k);
+            k = q;
         }
     }
 
     fn rho_prime(&self, k: Position) -> Extent {
-        check_backwards!(k);
+        let mut k = k;
 
-        let Extent(p0, q0) = self.a.rho_prime(k);
-        let Extent(p1, q1) = self.b.tau_prime(q0);
+        loop {
+            check_backwards!(k);
 
-        if p1 < p0 {
-            Extent(p0, q0)
-        } else {
-            // TODO: prevent recursion?
-            self.tau_prime(q1.decrement())
+            let Extent(p0, q0) = self.a.rho_prime(k);
+            let Extent(p1, q1) = self.b.tau_prime(q0);
+
+            if p1 < p0 {
+                return Extent(p0, q0);
+            }
+
+            // iteration instead of recursion; this is `self.tau_prime(q1 - 1)`
+            k = q1.decrement();
+            check_backwards!(k);
+            let Extent(p, _) = self.a.tau_prime(k);
+            k = p;
         }
     }
 }
@@ -1053,7 +1185,7 @@ where
 mod test {
     use quickcheck::{quickcheck, Arbitrary};
     use rand::Rng;
-    use std::{fmt::Debug, u32};
+    use std::fmt::Debug;
 
     use super::*;
 
@@ -1132,16 +1264,16 @@ mod test {
 
     impl Algebra for RandomExtentList {
         fn tau(&self, k: Position) -> Extent {
-            (&self.0[..]).tau(k)
+            self.0[..].tau(k)
         }
         fn tau_prime(&self, k: Position) -> Extent {
-            (&self.0[..]).tau_prime(k)
+            self.0[..].tau_prime(k)
         }
         fn rho(&self, k: Position) -> Extent {
-            (&self.0[..]).rho(k)
+            self.0[..].rho(k)
         }
         fn rho_prime(&self, k: Position) -> Extent {
-            (&self.0[..]).rho_prime(k)
+            self.0[..].rho_prime(k)
         }
     }
 
@@ -1166,6 +1298,10 @@ mod test {
         via_tau && via_rho && via_tau_prime && via_rho_prime
     }
 
+    fn consecutive_extents(count: u64) -> Vec<ValidExtent> {
+        (0..count).map(|i| (i * 2, i * 2 + 1)).collect()
+    }
+
     #[test]
     fn extent_list_all_tau_matches_all_rho() {
         fn prop(extents: RandomExtentList) -> bool {
@@ -1447,6 +1583,38 @@ mod test {
         assert_eq!(c.tau(1.into()), (2, 5));
     }
 
+    #[test]
+    fn not_contained_in_long_run_of_excluded_extents() {
+        let a = consecutive_extents(100_000);
+        let b = a.clone();
+        let c = NotContainedIn {
+            a: &a[..],
+            b: &b[..],
+        };
+
+        assert_eq!(c.tau(NegativeInfinity), END_EXTENT);
+        assert_eq!(c.rho(NegativeInfinity), END_EXTENT);
+        assert_eq!(c.tau_prime(PositiveInfinity), START_EXTENT);
+        assert_eq!(c.rho_prime(PositiveInfinity), START_EXTENT);
+    }
+
+    #[test]
+    fn not_contained_in_long_run_of_excluded_extents_with_survivors_at_ends() {
+        let a = consecutive_extents(100_000);
+        let b = &a[1..a.len() - 1];
+        let c = NotContainedIn { a: &a[..], b };
+
+        let first = a[0];
+        let last = a[a.len() - 1];
+
+        assert_eq!(c.tau(1.into()), last);
+        assert_eq!(c.rho(2.into()), last);
+        assert_eq!(c.tau_prime(Position::from(last.1 - 1)), first);
+        assert_eq!(c.rho_prime(Position::from(last.0 - 1)), first);
+        assert_eq!(all_extents(c), [first, last]);
+        assert!(c.iter_tau_prime().eq(vec![last, first]));
+    }
+
     #[test]
     fn not_containing_all_tau_matches_all_rho() {
         fn prop(a: RandomExtentList, b: RandomExtentList) -> bool {
@@ -1524,6 +1692,38 @@ mod test {
         assert_eq!(c.tau(1.into()), (1, 4));
     }
 
+    #[test]
+    fn not_containing_long_run_of_excluded_extents() {
+        let a = consecutive_extents(100_000);
+        let b = a.clone();
+        let c = NotContaining {
+            a: &a[..],
+            b: &b[..],
+        };
+
+        assert_eq!(c.tau(NegativeInfinity), END_EXTENT);
+        assert_eq!(c.rho(NegativeInfinity), END_EXTENT);
+        assert_eq!(c.tau_prime(PositiveInfinity), START_EXTENT);
+        assert_eq!(c.rho_prime(PositiveInfinity), START_EXTENT);
+    }
+
+    #[test]
+    fn not_containing_long_run_of_excluded_extents_with_survivors_at_ends() {
+        let a = consecutive_extents(100_000);
+        let b = &a[1..a.len() - 1];
+        let c = NotContaining { a: &a[..], b };
+
+        let first = a[0];
+        let last = a[a.len() - 1];
+
+        assert_eq!(c.tau(1.into()), last);
+        assert_eq!(c.rho(2.into()), last);
+        assert_eq!(c.tau_prime(Position::from(last.1 - 1)), first);
+        assert_eq!(c.rho_prime(Position::from(last.0 - 1)), first);
+        assert_eq!(all_extents(c), [first, last]);
+        assert!(c.iter_tau_prime().eq(vec![last, first]));
+    }
+
     #[test]
     fn both_of_all_tau_matches_all_rho() {
         fn prop(a: RandomExtentList, b: RandomExtentList) -> bool {
@@ -1955,6 +2155,18 @@ mod test {
         }
     }
 
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    #[test]
+    fn tree_of_operators_with_shared_leaves_is_thread_safe() {
+        let a: Arc<[ValidExtent]> = Arc::from(&[(1, 2), (5, 6)][..]);
+        let b: Arc<[ValidExtent]> = Arc::from(&[(0, 3)][..]);
+        let c = ContainedIn::new(OneOf::new(a.clone(), a), b);
+
+        assert_send_sync(&c);
+        assert_eq!(all_extents(&c), [(1, 2)]);
+    }
+
     #[test]
     fn tree_of_operators_all_tau_matches_all_rho() {
         fn prop(a: ArbitraryAlgebraTree) -> bool {
@@ -0,0 +1,240 @@
+use crate::{layer::trimmed, Index, NestedLayer, Tokenizer, ValidExtent};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+use std::collections::HashMap;
+
+/// A Markdown document, with layers for its structure.
+///
+/// The text is the Markdown source itself, so the layers are byte
+/// offsets into the source with an exclusive end, and the markers of
+/// a construct, such as the `#` of a heading or the backticks of
+/// inline code, are part of its region. Surrounding whitespace is not.
+///
+/// The layers are named after the HTML elements that the constructs
+/// become:
+///
+/// | Layer              | Construct                          |
+/// |--------------------|------------------------------------|
+/// | `h1` to `h6`       | Headings, by level                 |
+/// | `section`          | A heading and the text up to the next heading of the same or a higher level |
+/// | `section1` to `section6` | Sections, by the level of their heading |
+/// | `p`                | Paragraphs                         |
+/// | `li`               | List items                         |
+/// | `blockquote`       | Block quotes                       |
+/// | `pre`              | Indented and fenced code blocks    |
+/// | `code`             | Inline code                        |
+/// | `em`, `strong`     | Emphasis and strong emphasis       |
+/// | `a`                | Links                              |
+/// | `@href=...`        | Links to the destination           |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownDocument {
+    text: String,
+    layers: HashMap<String, Vec<ValidExtent>>,
+}
+
+impl MarkdownDocument {
+    /// Reads a CommonMark document. Any text is a valid document.
+    pub fn parse(source: impl Into<String>) -> Self {
+        let text = source.into();
+        let mut layers: HashMap<String, Vec<ValidExtent>> = HashMap::new();
+        let mut headings = Vec::new();
+
+        for (event, range) in Parser::new(&text).into_offset_iter() {
+            let name = match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    headings.push((level, range.start));
+                    heading_name(level).to_string()
+                }
+                Event::Start(Tag::Paragraph) => "p".to_string(),
+                Event::Start(Tag::Item) => "li".to_string(),
+                Event::Start(Tag::BlockQuote(_)) => "blockquote".to_string(),
+                Event::Start(Tag::CodeBlock(_)) => "pre".to_string(),
+                Event::Code(_) => "code".to_string(),
+                Event::Start(Tag::Emphasis) => "em".to_string(),
+                Event::Start(Tag::Strong) => "strong".to_string(),
+                Event::Start(Tag::Link { dest_url, .. }) => {
+                    let extent = trimmed(&text, range.start, range.end);
+                    layers
+                        .entry(format!("@href={}", dest_url))
+                        .or_default()
+                        .extend(extent);
+                    "a".to_string()
+                }
+                _ => continue,
+            };
+
+            let extent = trimmed(&text, range.start, range.end);
+            layers.entry(name).or_default().extend(extent);
+        }
+
+        for (i, &(level, start)) in headings.iter().enumerate() {
+            let end = headings[i + 1..]
+                .iter()
+                .find(|&&(next, _)| next <= level)
+                .map_or(text.len(), |&(_, next_start)| next_start);
+            let extent = trimmed(&text, start, end);
+            for name in ["section".to_string(), format!("section{}", level as usize)] {
+                layers.entry(name).or_default().extend(extent);
+            }
+        }
+
+        MarkdownDocument { text, layers }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// One layer for each kind of construct. Where constructs of the
+    /// same kind are nested, such as a list within a list item, only
+    /// the outermost is kept so that the layer is a GC-list in which
+    /// the words of nested items are still found;
+    /// [`nested_layers`](Self::nested_layers) keeps all of them. The
+    /// sections of one heading level never nest, so the layers from
+    /// `section1` to `section6` keep every section.
+    pub fn layers(&self) -> HashMap<String, Vec<ValidExtent>> {
+        self.nested_layers()
+            .into_iter()
+            .map(|(name, nested)| (name, nested.outermost().to_vec()))
+            .collect()
+    }
+
+    /// The same layers as [`layers`](Self::layers), keeping every
+    /// construct. The `section` of a heading contains the sections of
+    /// its subheadings.
+    pub fn nested_layers(&self) -> HashMap<String, NestedLayer> {
+        self.layers
+            .iter()
+            .map(|(name, extents)| (name.clone(), NestedLayer::new(extents.iter().copied())))
+            .collect()
+    }
+
+    /// Indexes the terms of the source along with every layer.
+    pub fn index<T>(&self, tokenizer: &T) -> Index
+    where
+        T: Tokenizer + ?Sized,
+    {
+        let mut index = tokenizer.index(&self.text);
+        for (name, extents) in self.layers() {
+            index.extend_layer(name, extents);
+        }
+        index
+    }
+}
+
+fn heading_name(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "h1",
+        HeadingLevel::H2 => "h2",
+        HeadingLevel::H3 => "h3",
+        HeadingLevel::H4 => "h4",
+        HeadingLevel::H5 => "h5",
+        HeadingLevel::H6 => "h6",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, AlphabeticTokenizer, Query};
+
+    const README: &str = "\
+# Widget
+
+Widgets are *very* useful.
+
+## Installation
+
+Run this:
+
+```sh
+cargo install widget
+```
+
+## Usage
+
+Call `widget::run` as [documented](https://example.com/docs).
+
+    cargo run
+";
+
+    fn layer<'a>(doc: &'a MarkdownDocument, name: &str) -> Vec<&'a str> {
+        doc.layers()
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|&(s, e)| &doc.text()[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn blocks_are_layers_over_the_source() {
+        let doc = MarkdownDocument::parse(README);
+
+        assert_eq!(doc.text(), README);
+        assert_eq!(layer(&doc, "h1"), ["# Widget"]);
+        assert_eq!(layer(&doc, "h2"), ["## Installation", "## Usage"]);
+        assert_eq!(
+            layer(&doc, "pre"),
+            ["```sh\ncargo install widget\n```", "cargo run"]
+        );
+        assert_eq!(layer(&doc, "p")[0], "Widgets are *very* useful.");
+    }
+
+    #[test]
+    fn inline_constructs_are_layers() {
+        let doc = MarkdownDocument::parse(README);
+
+        assert_eq!(layer(&doc, "em"), ["*very*"]);
+        assert_eq!(layer(&doc, "code"), ["`widget::run`"]);
+        assert_eq!(layer(&doc, "a"), ["[documented](https://example.com/docs)"]);
+        assert_eq!(
+            layer(&doc, "@href=https://example.com/docs"),
+            ["[documented](https://example.com/docs)"]
+        );
+    }
+
+    #[test]
+    fn sections_nest_by_heading_level() {
+        let doc = MarkdownDocument::parse(README);
+        let sections = &doc.nested_layers()["section"];
+
+        assert_eq!(sections.outermost(), [(0, README.trim_end().len() as u64)]);
+        assert_eq!(sections.level(1).len(), 2);
+        assert_eq!(layer(&doc, "section"), [README.trim_end()]);
+        assert_eq!(layer(&doc, "section1"), [README.trim_end()]);
+        assert_eq!(layer(&doc, "section2").len(), 2);
+        assert!(layer(&doc, "section2")[0].starts_with("## Installation"));
+        assert!(layer(&doc, "section2")[0].ends_with("```"));
+    }
+
+    #[test]
+    fn code_blocks_within_a_section_are_found() {
+        let doc = MarkdownDocument::parse(README);
+        let index = doc.index(&AlphabeticTokenizer);
+
+        let installation = Query::Containing(
+            Box::new(Query::Layer("section2".into())),
+            Box::new(Query::Containing(
+                Box::new(Query::Layer("h2".into())),
+                Box::new(Query::Term("installation".into())),
+            )),
+        );
+        let query =
+            Query::ContainedIn(Box::new(Query::Layer("pre".into())), Box::new(installation));
+        let found: Vec<_> = query
+            .evaluate(&index)
+            .iter_tau()
+            .map(|(s, e)| &README[s as usize..e as usize])
+            .collect();
+
+        assert_eq!(found, ["```sh\ncargo install widget\n```"]);
+    }
+
+    #[test]
+    fn nested_list_items_keep_the_outermost() {
+        let doc = MarkdownDocument::parse("- one\n  - two\n- three\n");
+
+        assert_eq!(layer(&doc, "li"), ["- one\n  - two", "- three"]);
+        assert_eq!(doc.nested_layers()["li"].len(), 3);
+    }
+}
@@ -0,0 +1,789 @@
+use crate::{Index, NestedLayer, Tokenizer, ValidExtent};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// A reason that an XML document could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkupError {
+    /// A tag, comment or other markup construct is not well-formed.
+    Malformed { offset: usize },
+    /// An end tag has no matching start tag.
+    UnexpectedEndTag { name: String, offset: usize },
+    /// An end tag does not match the innermost open element.
+    MismatchedEndTag {
+        expected: String,
+        found: String,
+        offset: usize,
+    },
+    /// The document ended before the element was closed.
+    UnclosedElement { name: String },
+    /// An entity reference is not one of the predefined entities or a
+    /// character reference.
+    UnknownEntity { entity: String, offset: usize },
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkupError::Malformed { offset } => write!(f, "malformed markup at byte {}", offset),
+            MarkupError::UnexpectedEndTag { name, offset } => {
+                write!(f, "end tag </{}> at byte {} has no start tag", name, offset)
+            }
+            MarkupError::MismatchedEndTag {
+                expected,
+                found,
+                offset,
+            } => write!(
+                f,
+                "end tag </{}> at byte {} does not close <{}>",
+                found, offset, expected
+            ),
+            MarkupError::UnclosedElement { name } => {
+                write!(f, "element <{}> is never closed", name)
+            }
+            MarkupError::UnknownEntity { entity, offset } => {
+                write!(f, "unknown entity {} at byte {}", entity, offset)
+            }
+        }
+    }
+}
+
+impl Error for MarkupError {}
+
+/// An element of a [`MarkupDocument`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    extent: ValidExtent,
+    depth: usize,
+}
+
+impl Element {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The attribute names and their values, with entities decoded.
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    /// The text content of the element, as byte offsets into the
+    /// document's text with an exclusive end.
+    pub fn extent(&self) -> ValidExtent {
+        self.extent
+    }
+
+    /// The number of elements enclosing this one.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// The text content of an XML or HTML document, with its elements.
+///
+/// Tags, comments and processing instructions are removed from the
+/// text, and entity references are replaced by the characters they
+/// stand for. Each element then covers the part of the text between
+/// its start and end tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkupDocument {
+    text: String,
+    // In document order
+    elements: Vec<Element>,
+}
+
+impl MarkupDocument {
+    /// Reads a well-formed XML document.
+    ///
+    /// Only the predefined entities and character references are
+    /// understood, and element and attribute names are kept as
+    /// written. The text is kept exactly, so the words of adjacent
+    /// elements run together unless whitespace separates them.
+    pub fn parse_xml(source: &str) -> Result<Self, MarkupError> {
+        Parser::new(source, false).run()
+    }
+
+    /// Reads an HTML document as a browser would, without failing.
+    ///
+    /// Names are lowercased, void elements such as `<br>` need no end
+    /// tag, and elements such as `<p>` and `<li>` are closed by the
+    /// start of the next one. The content of `<script>` and `<style>`
+    /// is not part of the text. A line break is inserted between block
+    /// elements so that their words do not run together.
+    pub fn parse_html(source: &str) -> Self {
+        Parser::new(source, true)
+            .run()
+            .expect("Reading HTML never fails")
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The elements, ordered by the position of their start tags.
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    /// One layer for each element name, and one for each attribute
+    /// and value, named like `@lang=en`. An attribute with an empty
+    /// value is named like `@hidden`.
+    ///
+    /// Elements without any text are omitted. Where elements of the
+    /// same layer are nested, only the outermost is kept so that the
+    /// layer is a GC-list that still contains the text of every
+    /// element; [`nested_layers`](Self::nested_layers) keeps all of
+    /// them.
+    pub fn layers(&self) -> HashMap<String, Vec<ValidExtent>> {
+        self.nested_layers()
+            .into_iter()
+            .map(|(name, nested)| (name, nested.outermost().to_vec()))
+            .collect()
+    }
+
+    /// The same layers as [`layers`](Self::layers), keeping every
+    /// element of nested elements such as `<section>`.
+    pub fn nested_layers(&self) -> HashMap<String, NestedLayer> {
+        let mut layers: HashMap<String, Vec<ValidExtent>> = HashMap::new();
+
+        for element in &self.elements {
+            if element.extent.0 == element.extent.1 {
+                continue;
+            }
+
+            layers
+                .entry(element.name.clone())
+                .or_default()
+                .push(element.extent);
+            for (name, value) in &element.attributes {
+                layers
+                    .entry(attribute_layer(name, value))
+                    .or_default()
+                    .push(element.extent);
+            }
+        }
+
+        layers
+            .into_iter()
+            .map(|(name, extents)| (name, NestedLayer::new(extents)))
+            .collect()
+    }
+
+    /// Indexes the terms of the text along with every layer.
+    pub fn index<T>(&self, tokenizer: &T) -> Index
+    where
+        T: Tokenizer + ?Sized,
+    {
+        let mut index = tokenizer.index(&self.text);
+        for (name, extents) in self.layers() {
+            index.extend_layer(name, extents);
+        }
+        index
+    }
+}
+
+fn attribute_layer(name: &str, value: &str) -> String {
+    if value.is_empty() {
+        format!("@{}", name)
+    } else {
+        format!("@{}={}", name, value)
+    }
+}
+
+// Elements that separate their content from the surrounding text and
+// end an open paragraph.
+const HTML_BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "ul",
+];
+
+const HTML_VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+// Elements whose content is not text
+const HTML_RAW: &[&str] = &["script", "style"];
+
+struct Open {
+    name: String,
+    attributes: Vec<(String, String)>,
+    start: usize,
+    depth: usize,
+}
+
+struct Parser<'s> {
+    source: &'s str,
+    pos: usize,
+    html: bool,
+    text: String,
+    open: Vec<Open>,
+    elements: Vec<Element>,
+}
+
+impl<'s> Parser<'s> {
+    fn new(source: &'s str, html: bool) -> Self {
+        Parser {
+            source,
+            pos: 0,
+            html,
+            text: String::new(),
+            open: Vec::new(),
+            elements: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<MarkupDocument, MarkupError> {
+        while self.pos < self.source.len() {
+            let rest = &self.source[self.pos..];
+            match rest.find(['<', '&']) {
+                None => {
+                    self.text.push_str(rest);
+                    self.pos = self.source.len();
+                }
+                Some(0) if rest.starts_with('<') => self.markup()?,
+                Some(0) => self.entity()?,
+                Some(len) => {
+                    self.text.push_str(&rest[..len]);
+                    self.pos += len;
+                }
+            }
+        }
+
+        if let (false, Some(open)) = (self.html, self.open.last()) {
+            return Err(MarkupError::UnclosedElement {
+                name: open.name.clone(),
+            });
+        }
+        while let Some(open) = self.open.pop() {
+            self.finish(open);
+        }
+
+        let mut elements = self.elements;
+        elements.sort_by_key(|e| (e.extent.0, e.depth));
+
+        Ok(MarkupDocument {
+            text: self.text,
+            elements,
+        })
+    }
+
+    fn markup(&mut self) -> Result<(), MarkupError> {
+        let rest = &self.source[self.pos..];
+
+        if rest.starts_with("<!--") {
+            self.skip_past("-->")
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let len = cdata
+                .find("]]>")
+                .ok_or(MarkupError::Malformed { offset: self.pos })?;
+            self.text.push_str(&cdata[..len]);
+            self.pos += "<![CDATA[".len() + len + "]]>".len();
+            Ok(())
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            self.skip_past(">")
+        } else if rest.starts_with("</") {
+            self.end_tag()
+        } else if rest[1..].starts_with(|c: char| c.is_alphabetic() || c == '_' || c == ':') {
+            self.start_tag()
+        } else if self.html {
+            self.text.push('<');
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(MarkupError::Malformed { offset: self.pos })
+        }
+    }
+
+    fn skip_past(&mut self, delimiter: &str) -> Result<(), MarkupError> {
+        match self.source[self.pos..].find(delimiter) {
+            Some(len) => self.pos += len + delimiter.len(),
+            None if self.html => self.pos = self.source.len(),
+            None => return Err(MarkupError::Malformed { offset: self.pos }),
+        }
+        Ok(())
+    }
+
+    fn start_tag(&mut self) -> Result<(), MarkupError> {
+        let source = self.source;
+        let offset = self.pos;
+        let malformed = || MarkupError::Malformed { offset };
+
+        let mut i = offset + 1;
+        let name_end = scan(source, i, |c| !(c.is_whitespace() || c == '>' || c == '/'));
+        let name = self.name(&source[i..name_end]);
+        i = name_end;
+
+        let mut attributes = Vec::new();
+        let self_closing = loop {
+            i = scan(source, i, char::is_whitespace);
+            let rest = &source[i..];
+
+            if rest.is_empty() {
+                if self.html {
+                    self.pos = source.len();
+                    return Ok(());
+                }
+                return Err(malformed());
+            } else if rest.starts_with('>') {
+                i += 1;
+                break false;
+            } else if rest.starts_with("/>") {
+                i += 2;
+                break true;
+            }
+
+            let attr_end = scan(source, i, |c| {
+                !(c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            });
+            if attr_end == i {
+                if self.html {
+                    i += 1;
+                    continue;
+                }
+                return Err(malformed());
+            }
+            let attr = self.name(&source[i..attr_end]);
+            i = scan(source, attr_end, char::is_whitespace);
+
+            let value = if source[i..].starts_with('=') {
+                i = scan(source, i + 1, char::is_whitespace);
+                let (raw, next) = match source[i..].chars().next() {
+                    Some(quote @ '"') | Some(quote @ '\'') => {
+                        let len = source[i + 1..].find(quote).ok_or_else(malformed)?;
+                        (&source[i + 1..i + 1 + len], i + len + 2)
+                    }
+                    Some(_) if self.html => {
+                        let end = scan(source, i, |c| !(c.is_whitespace() || c == '>'));
+                        (&source[i..end], end)
+                    }
+                    _ => return Err(malformed()),
+                };
+                i = next;
+                self.decode(raw, offset)?
+            } else if self.html {
+                String::new()
+            } else {
+                return Err(malformed());
+            };
+
+            attributes.push((attr, value));
+        };
+        self.pos = i;
+
+        if self.html {
+            if HTML_BLOCKS.contains(&name.as_str()) {
+                self.close_open(&["p"], HTML_BLOCKS);
+            }
+            match name.as_str() {
+                "li" => self.close_open(&["li"], &["ul", "ol"]),
+                "dt" | "dd" => self.close_open(&["dt", "dd"], &["dl"]),
+                "tr" => self.close_open(&["tr"], &["table", "thead", "tbody", "tfoot"]),
+                "td" | "th" => self.close_open(&["td", "th"], &["tr"]),
+                "option" => self.close_open(&["option"], &["select"]),
+                _ => {}
+            }
+            self.separate(&name);
+        }
+
+        let open = Open {
+            depth: self.open.len(),
+            start: self.text.len(),
+            name,
+            attributes,
+        };
+
+        if self_closing || (self.html && HTML_VOID.contains(&open.name.as_str())) {
+            self.finish(open);
+        } else if self.html && HTML_RAW.contains(&open.name.as_str()) {
+            // Skip to the end tag, which is then read as usual
+            let end_tag = format!("</{}", open.name);
+            let rest = source[self.pos..].to_ascii_lowercase();
+            self.pos = rest
+                .find(&end_tag)
+                .map_or(source.len(), |len| self.pos + len);
+            self.open.push(open);
+        } else {
+            self.open.push(open);
+        }
+
+        Ok(())
+    }
+
+    fn end_tag(&mut self) -> Result<(), MarkupError> {
+        let offset = self.pos;
+        let len = match self.source[offset..].find('>') {
+            Some(len) => len,
+            None if self.html => {
+                self.pos = self.source.len();
+                return Ok(());
+            }
+            None => return Err(MarkupError::Malformed { offset }),
+        };
+        let name = self.name(self.source[offset + 2..offset + len].trim());
+        self.pos = offset + len + 1;
+
+        let idx = self.open.iter().rposition(|o| o.name == name);
+        match (idx, self.open.last()) {
+            (Some(idx), _) if self.html || idx + 1 == self.open.len() => {
+                self.close_from(idx);
+                if self.html {
+                    self.separate(&name);
+                }
+                Ok(())
+            }
+            (None, _) if self.html => Ok(()),
+            (_, Some(innermost)) => Err(MarkupError::MismatchedEndTag {
+                expected: innermost.name.clone(),
+                found: name,
+                offset,
+            }),
+            (_, None) => Err(MarkupError::UnexpectedEndTag { name, offset }),
+        }
+    }
+
+    // Closes the innermost open element named in `closes`, unless an
+    // element named in `scope` is nested more deeply.
+    fn close_open(&mut self, closes: &[&str], scope: &[&str]) {
+        let found = self.open.iter().rposition(|o| {
+            let name = o.name.as_str();
+            closes.contains(&name) || scope.contains(&name)
+        });
+        if let Some(idx) = found {
+            if closes.contains(&self.open[idx].name.as_str()) {
+                self.close_from(idx);
+            }
+        }
+    }
+
+    // Closes the open element at `idx` and everything inside of it
+    fn close_from(&mut self, idx: usize) {
+        while self.open.len() > idx {
+            let open = self.open.pop().expect("An element is open");
+            self.finish(open);
+        }
+    }
+
+    fn finish(&mut self, open: Open) {
+        self.elements.push(Element {
+            name: open.name,
+            attributes: open.attributes,
+            extent: (open.start as u64, self.text.len() as u64),
+            depth: open.depth,
+        });
+    }
+
+    // Keeps the words of adjacent HTML blocks apart
+    fn separate(&mut self, name: &str) {
+        let is_break = name == "br" || HTML_BLOCKS.contains(&name);
+        if is_break && !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+            self.text.push('\n');
+        }
+    }
+
+    fn name(&self, name: &str) -> String {
+        if self.html {
+            name.to_ascii_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn entity(&mut self) -> Result<(), MarkupError> {
+        let rest = &self.source[self.pos..];
+        match entity(rest, self.html) {
+            Some((c, len)) => {
+                self.text.push(c);
+                self.pos += len;
+                Ok(())
+            }
+            None if self.html => {
+                self.text.push('&');
+                self.pos += 1;
+                Ok(())
+            }
+            None => Err(unknown_entity(rest, self.pos)),
+        }
+    }
+
+    fn decode(&self, raw: &str, offset: usize) -> Result<String, MarkupError> {
+        let mut decoded = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(idx) = rest.find('&') {
+            decoded.push_str(&rest[..idx]);
+            rest = &rest[idx..];
+            match entity(rest, self.html) {
+                Some((c, len)) => {
+                    decoded.push(c);
+                    rest = &rest[len..];
+                }
+                None if self.html => {
+                    decoded.push('&');
+                    rest = &rest[1..];
+                }
+                None => return Err(unknown_entity(rest, offset)),
+            }
+        }
+        decoded.push_str(rest);
+        Ok(decoded)
+    }
+}
+
+// The position of the first character after `from` not matching
+fn scan(s: &str, from: usize, matching: impl Fn(char) -> bool) -> usize {
+    s[from..]
+        .find(|c| !matching(c))
+        .map_or(s.len(), |len| from + len)
+}
+
+// The character for the entity reference at the start of `s`, and
+// the length of the reference
+fn entity(s: &str, html: bool) -> Option<(char, usize)> {
+    let len = s.get(..32).unwrap_or(s).find(';')?;
+    let name = &s[1..len];
+
+    let c = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+    } else if let Some(decimal) = name.strip_prefix('#') {
+        char::from_u32(decimal.parse().ok()?)?
+    } else {
+        match (name, html) {
+            ("amp", _) => '&',
+            ("lt", _) => '<',
+            ("gt", _) => '>',
+            ("quot", _) => '"',
+            ("apos", _) => '\'',
+            ("nbsp", true) => '\u{a0}',
+            ("copy", true) => '©',
+            ("reg", true) => '®',
+            ("trade", true) => '™',
+            ("mdash", true) => '—',
+            ("ndash", true) => '–',
+            ("hellip", true) => '…',
+            ("lsquo", true) => '‘',
+            ("rsquo", true) => '’',
+            ("ldquo", true) => '“',
+            ("rdquo", true) => '”',
+            ("times", true) => '×',
+            _ => return None,
+        }
+    };
+
+    Some((c, len + 1))
+}
+
+fn unknown_entity(s: &str, offset: usize) -> MarkupError {
+    let end = s.find(';').map_or(s.len(), |len| len + 1);
+    let entity = s[..end].chars().take(32).collect();
+    MarkupError::UnknownEntity { entity, offset }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, AlphabeticTokenizer, Query};
+
+    fn layer<'a>(doc: &'a MarkupDocument, name: &str) -> Vec<&'a str> {
+        doc.layers()
+            .remove(name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(s, e)| &doc.text()[s as usize..e as usize])
+            .collect()
+    }
+
+    fn evaluate(index: &Index, query: Query) -> Vec<ValidExtent> {
+        query.evaluate(index).iter_tau().collect()
+    }
+
+    const DOCBOOK: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE article>
+<article lang="en"><title>Hello &amp; welcome</title>
+<section xml:id="intro"><title>Intro</title> <para>Hello, world!</para></section>
+<section><title>Outro</title><para lang="fr">Au revoir, world<![CDATA[ <3]]></para><!-- done --></section>
+</article>"#;
+
+    #[test]
+    fn xml_elements_become_layers() {
+        let doc = MarkupDocument::parse_xml(DOCBOOK).unwrap();
+
+        assert_eq!(layer(&doc, "title"), ["Hello & welcome", "Intro", "Outro"]);
+        assert_eq!(
+            layer(&doc, "para"),
+            ["Hello, world!", "Au revoir, world <3"]
+        );
+        assert_eq!(layer(&doc, "@lang=fr"), ["Au revoir, world <3"]);
+        assert_eq!(layer(&doc, "@xml:id=intro"), ["Intro Hello, world!"]);
+        assert_eq!(layer(&doc, "section").len(), 2);
+        assert!(!doc.text().contains("done"));
+    }
+
+    #[test]
+    fn xml_elements_are_in_document_order_with_depth() {
+        let doc = MarkupDocument::parse_xml("<a><b>x</b><c><d>y</d></c></a>").unwrap();
+
+        let found: Vec<_> = doc
+            .elements()
+            .iter()
+            .map(|e| (e.name(), e.extent(), e.depth()))
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                ("a", (0, 2), 0),
+                ("b", (0, 1), 1),
+                ("c", (1, 2), 1),
+                ("d", (1, 2), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn xml_layers_answer_queries() {
+        let doc = MarkupDocument::parse_xml(DOCBOOK).unwrap();
+        let index = doc.index(&AlphabeticTokenizer);
+
+        let world_in_french = Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(Query::Layer("@lang=fr".into())),
+        );
+        let sections_with_intro = Query::Containing(
+            Box::new(Query::Layer("section".into())),
+            Box::new(Query::Term("intro".into())),
+        );
+
+        let found = evaluate(&index, world_in_french);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            &doc.text()[found[0].0 as usize..found[0].1 as usize],
+            "world"
+        );
+        assert_eq!(evaluate(&index, sections_with_intro).len(), 1);
+    }
+
+    #[test]
+    fn malformed_xml_is_rejected() {
+        assert_eq!(
+            MarkupDocument::parse_xml("<a><b></a></b>"),
+            Err(MarkupError::MismatchedEndTag {
+                expected: "b".into(),
+                found: "a".into(),
+                offset: 6,
+            })
+        );
+        assert_eq!(
+            MarkupDocument::parse_xml("<a>x</a></b>"),
+            Err(MarkupError::UnexpectedEndTag {
+                name: "b".into(),
+                offset: 8,
+            })
+        );
+        assert_eq!(
+            MarkupDocument::parse_xml("<a><b>"),
+            Err(MarkupError::UnclosedElement { name: "b".into() })
+        );
+        assert_eq!(
+            MarkupDocument::parse_xml("<a>&nbsp;</a>"),
+            Err(MarkupError::UnknownEntity {
+                entity: "&nbsp;".into(),
+                offset: 3,
+            })
+        );
+        assert_eq!(
+            MarkupDocument::parse_xml("<a x=1></a>"),
+            Err(MarkupError::Malformed { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn html_is_read_leniently() {
+        let doc = MarkupDocument::parse_html(
+            r#"<!doctype html><HTML lang=en><head><title>Page</title>
+<style>p { color: red }</style><script>if (a < b) {}</script></head>
+<body><p>First&nbsp;para<br>line two<p CLASS="note">Second <b>bold</b> & more
+<ul><li>one<li>two</ul><img src="x.png" alt="pic"><input disabled></body></html>"#,
+        );
+
+        assert_eq!(layer(&doc, "title"), ["Page"]);
+        assert_eq!(
+            layer(&doc, "p"),
+            ["First\u{a0}para\nline two", "Second bold & more\n"]
+        );
+        assert_eq!(layer(&doc, "@class=note"), ["Second bold & more\n"]);
+        assert_eq!(layer(&doc, "li"), ["one", "two"]);
+        assert_eq!(layer(&doc, "b"), ["bold"]);
+        assert!(layer(&doc, "img").is_empty());
+        assert!(!doc.text().contains("color"));
+        assert!(!doc.text().contains("if"));
+        assert!(doc
+            .elements()
+            .iter()
+            .any(|e| e.name() == "input"
+                && e.attributes() == [("disabled".to_string(), String::new())]));
+    }
+
+    #[test]
+    fn html_blocks_keep_words_apart() {
+        let doc = MarkupDocument::parse_html("<div>one</div><div>two</div>three<br>four");
+
+        assert_eq!(doc.text(), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn nested_elements_keep_the_outermost() {
+        let doc = MarkupDocument::parse_xml("<s>a <s>b</s> c <s>d</s></s>").unwrap();
+
+        assert_eq!(layer(&doc, "s"), ["a b c d"]);
+        assert_eq!(doc.elements().len(), 3);
+    }
+
+    #[test]
+    fn nested_layers_keep_every_element() {
+        let doc = MarkupDocument::parse_xml("<s>a <s>b</s> c <s>d</s></s>").unwrap();
+        let nested = &doc.nested_layers()["s"];
+
+        assert_eq!(nested.outermost(), [(0, 7)]);
+        assert_eq!(nested.level(1), [(2, 3), (6, 7)]);
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{Algebra, Extent, Index, Position, Query, Source, ValidExtent};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// An algebra whose extents have all been found up front.
+///
+/// Evaluating an operator tree repeatedly performs the same work each
+/// time. Materializing it once trades memory for that work, answering
+/// every access function with a binary search. The operator tree is
+/// dropped once its extents are collected.
+///
+/// Although any [`Algebra`] may be materialized, the type does not
+/// name the algebra it came from. Operator trees usually borrow the
+/// index they read, and a type parameter would carry that borrow
+/// along with it, even though the extents no longer need the index.
+/// Without one, results of different trees also share a single type,
+/// as in a [`QueryCache`].
+#[derive(Debug, Clone)]
+pub struct Materialized {
+    extents: Arc<[ValidExtent]>,
+}
+
+impl Materialized {
+    /// Immediately collects every extent of the algebra.
+    pub fn new<A>(source: A) -> Self
+    where
+        A: Algebra,
+    {
+        let extents = source.iter_tau().collect();
+        Materialized { extents }
+    }
+
+    pub fn extents(&self) -> &[ValidExtent] {
+        &self.extents
+    }
+
+    /// The extents, shareable without copying them.
+    pub fn shared_extents(&self) -> Arc<[ValidExtent]> {
+        self.extents.clone()
+    }
+}
+
+impl Algebra for Materialized {
+    fn tau(&self, k: Position) -> Extent {
+        self.extents.tau(k)
+    }
+    fn tau_prime(&self, k: Position) -> Extent {
+        self.extents.tau_prime(k)
+    }
+    fn rho(&self, k: Position) -> Extent {
+        self.extents.rho(k)
+    }
+    fn rho_prime(&self, k: Position) -> Extent {
+        self.extents.rho_prime(k)
+    }
+}
+
+/// When a [`QueryCache`] should materialize a subquery instead of
+/// evaluating it lazily.
+///
+/// Term and layer lookups are already extent lists and are never
+/// materialized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Materialization {
+    /// Always evaluate lazily; the cache stores nothing.
+    Lazy,
+    /// Materialize every operator the first time it is seen.
+    Eager,
+    /// Materialize an operator once its structure has been seen this
+    /// many times, counting repeats within a single query.
+    AfterUses(usize),
+}
+
+impl Materialization {
+    fn threshold(self) -> Option<usize> {
+        match self {
+            Materialization::Lazy => None,
+            Materialization::Eager => Some(1),
+            Materialization::AfterUses(n) => Some(n),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Entry {
+    Seen(usize),
+    Materialized(Arc<[ValidExtent]>),
+}
+
+#[derive(Debug)]
+struct Slot {
+    entry: Entry,
+    last_used: u64,
+}
+
+// The entries of a cache, each stamped with when it was last used
+#[derive(Debug, Default)]
+struct Entries {
+    slots: HashMap<Query, Slot>,
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, query: &Query) -> Option<&Entry> {
+        let now = self.tick();
+        let slot = self.slots.get_mut(query)?;
+        slot.last_used = now;
+        Some(&slot.entry)
+    }
+
+    fn count_use(&mut self, query: &Query, capacity: usize) {
+        let now = self.tick();
+        let slot = self.slots.entry(query.clone()).or_insert(Slot {
+            entry: Entry::Seen(0),
+            last_used: now,
+        });
+        slot.last_used = now;
+        if let Entry::Seen(n) = &mut slot.entry {
+            *n += 1;
+        }
+        self.evict(capacity);
+    }
+
+    fn insert(&mut self, query: Query, entry: Entry, capacity: usize) {
+        let last_used = self.tick();
+        self.slots.insert(query, Slot { entry, last_used });
+        self.evict(capacity);
+    }
+
+    // Once over capacity, keeps only the most recently used half so
+    // that the cost of finding them is shared by many insertions.
+    fn evict(&mut self, capacity: usize) {
+        if self.slots.len() <= capacity {
+            return;
+        }
+
+        let keep = capacity / 2;
+        if keep == 0 {
+            self.slots.clear();
+            return;
+        }
+
+        let mut times: Vec<_> = self.slots.values().map(|s| s.last_used).collect();
+        let (_, &mut oldest_kept, _) = times.select_nth_unstable_by(keep - 1, |a, b| b.cmp(a));
+        self.slots.retain(|_, s| s.last_used >= oldest_kept);
+    }
+}
+
+/// Reuses the results of subqueries across the queries evaluated
+/// against one index or other source.
+///
+/// The cache holds at most [`QueryCache::DEFAULT_CAPACITY`]
+/// subqueries unless given another capacity. When it is full, the
+/// subqueries used least recently are forgotten, whether they were
+/// materialized or only counted towards the policy's threshold.
+///
+/// The cache may be shared between threads evaluating queries
+/// concurrently. It must be cleared if the index changes.
+#[derive(Debug)]
+pub struct QueryCache<'i, S: ?Sized = Index> {
+    source: &'i S,
+    policy: Materialization,
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+impl<'i, S> QueryCache<'i, S>
+where
+    S: Source + ?Sized,
+{
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new(source: &'i S, policy: Materialization) -> Self {
+        QueryCache {
+            source,
+            policy,
+            capacity: Self::DEFAULT_CAPACITY,
+            entries: Default::default(),
+        }
+    }
+
+    /// Sets the number of subqueries the cache holds, counting those
+    /// that are not yet materialized.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self.entries().evict(capacity);
+        self
+    }
+
+    pub fn policy(&self) -> Materialization {
+        self.policy
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Builds the operator tree for the query, materializing and
+    /// reusing subqueries according to the policy.
+    pub fn evaluate(&self, query: &Query) -> Box<dyn Algebra + Send + Sync + 'i> {
+        let threshold = match self.policy.threshold() {
+            Some(t) => t,
+            None => return query.evaluate(self.source),
+        };
+
+        self.record_uses(query);
+        self.evaluate_inner(query, threshold)
+    }
+
+    /// The number of materialized subqueries.
+    pub fn materialized_len(&self) -> usize {
+        self.entries()
+            .slots
+            .values()
+            .filter(|s| matches!(s.entry, Entry::Materialized(_)))
+            .count()
+    }
+
+    pub fn clear(&self) {
+        self.entries().slots.clear();
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        // The map is never left in an inconsistent state, so a panic
+        // while the lock was held does not invalidate it.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record_uses(&self, query: &Query) {
+        if let Some((a, b)) = query.children() {
+            self.entries().count_use(query, self.capacity);
+            self.record_uses(a);
+            self.record_uses(b);
+        }
+    }
+
+    fn evaluate_inner(
+        &self,
+        query: &Query,
+        threshold: usize,
+    ) -> Box<dyn Algebra + Send + Sync + 'i> {
+        let cached = match self.entries().get(query) {
+            Some(Entry::Materialized(extents)) => Some(Ok(extents.clone())),
+            Some(Entry::Seen(n)) if *n >= threshold => Some(Err(())),
+            _ => None,
+        };
+
+        match cached {
+            Some(Ok(extents)) => Box::new(extents),
+            Some(Err(())) => {
+                let lazy =
+                    query.evaluate_with(self.source, &mut |q, _| self.evaluate_inner(q, threshold));
+                let extents = Materialized::new(lazy).shared_extents();
+                self.entries().insert(
+                    query.clone(),
+                    Entry::Materialized(extents.clone()),
+                    self.capacity,
+                );
+                Box::new(extents)
+            }
+            None => query.evaluate_with(self.source, &mut |q, _| self.evaluate_inner(q, threshold)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ContainedIn, Containing};
+
+    fn sample_index() -> Index {
+        let mut index = Index::new();
+        index.extend_term("hello", vec![(0, 5), (30, 35)]);
+        index.extend_term("world", vec![(7, 12), (23, 28)]);
+        index.extend_layer("sentence", vec![(0, 13), (14, 29), (30, 40)]);
+        index
+    }
+
+    fn sentences_with_hello() -> Query {
+        Query::Containing(
+            Box::new(Query::Layer("sentence".into())),
+            Box::new(Query::Term("hello".into())),
+        )
+    }
+
+    #[test]
+    fn materialized_matches_lazy_evaluation() {
+        let a = [(1, 2), (3, 4), (20, 21)];
+        let b = [(0, 10)];
+        let lazy = ContainedIn::new(&a[..], &b[..]);
+        let materialized = Materialized::new(lazy);
+
+        assert_eq!(materialized.extents(), [(1, 2), (3, 4)]);
+        assert!((&materialized).iter_tau().eq(lazy.iter_tau()));
+        assert!(materialized.iter_rho_prime().eq(lazy.iter_rho_prime()));
+    }
+
+    #[test]
+    fn materialized_extents_outlive_the_index() {
+        let materialized = {
+            let index = sample_index();
+            Materialized::new(sentences_with_hello().evaluate(&index))
+        };
+
+        assert_eq!(materialized.extents(), [(0, 13), (30, 40)]);
+    }
+
+    #[test]
+    fn lazy_policy_never_materializes() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::Lazy);
+
+        let found: Vec<_> = cache.evaluate(&sentences_with_hello()).iter_tau().collect();
+
+        assert_eq!(found, [(0, 13), (30, 40)]);
+        assert_eq!(cache.materialized_len(), 0);
+    }
+
+    #[test]
+    fn eager_policy_materializes_every_operator() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::Eager);
+        let query = Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(sentences_with_hello()),
+        );
+
+        let found: Vec<_> = cache.evaluate(&query).iter_tau().collect();
+
+        assert_eq!(found, [(7, 12)]);
+        assert_eq!(cache.materialized_len(), 2);
+    }
+
+    #[test]
+    fn repeated_subquery_within_one_query_is_materialized() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::AfterUses(2));
+        let query = Query::OneOf(
+            Box::new(sentences_with_hello()),
+            Box::new(sentences_with_hello()),
+        );
+
+        let found: Vec<_> = cache.evaluate(&query).iter_tau().collect();
+
+        assert_eq!(found, [(0, 13), (30, 40)]);
+        assert_eq!(cache.materialized_len(), 1);
+    }
+
+    #[test]
+    fn subquery_is_materialized_across_queries() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::AfterUses(2));
+        let first = Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(sentences_with_hello()),
+        );
+        let second = Query::ContainedIn(
+            Box::new(Query::Term("hello".into())),
+            Box::new(sentences_with_hello()),
+        );
+
+        let found: Vec<_> = cache.evaluate(&first).iter_tau().collect();
+        assert_eq!(found, [(7, 12)]);
+        assert_eq!(cache.materialized_len(), 0);
+
+        let found: Vec<_> = cache.evaluate(&second).iter_tau().collect();
+        assert_eq!(found, [(0, 5), (30, 35)]);
+        assert_eq!(cache.materialized_len(), 1);
+
+        cache.clear();
+        assert_eq!(cache.materialized_len(), 0);
+    }
+
+    #[test]
+    fn cached_results_match_lazy_operator() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::Eager);
+        let lazy = Containing::new(index.layer("sentence"), index.term("hello"));
+
+        let cached = cache.evaluate(&sentences_with_hello());
+
+        assert!((&cached).iter_tau_prime().eq(lazy.iter_tau_prime()));
+    }
+
+    #[test]
+    fn cache_is_shared_between_threads() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::Eager);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let found: Vec<_> =
+                        cache.evaluate(&sentences_with_hello()).iter_tau().collect();
+                    assert_eq!(found, [(0, 13), (30, 40)]);
+                });
+            }
+        });
+
+        assert_eq!(cache.materialized_len(), 1);
+    }
+
+    #[test]
+    fn cache_forgets_the_least_recently_used_subqueries() {
+        let index = sample_index();
+        let cache = QueryCache::new(&index, Materialization::AfterUses(2)).with_capacity(4);
+        let in_sentence = |term: &str| {
+            Query::ContainedIn(
+                Box::new(Query::Term(term.into())),
+                Box::new(Query::Layer("sentence".into())),
+            )
+        };
+
+        for i in 0..100 {
+            cache.evaluate(&in_sentence(&i.to_string()));
+            cache.evaluate(&sentences_with_hello());
+            assert!(cache.entries().slots.len() <= 4);
+        }
+
+        assert_eq!(cache.materialized_len(), 1);
+        assert!(cache.entries().get(&sentences_with_hello()).is_some());
+        assert!(cache.entries().get(&in_sentence("0")).is_none());
+    }
+}
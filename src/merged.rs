@@ -0,0 +1,208 @@
+use crate::{Algebra, Extent, Position, ValidExtent, END_EXTENT, START_EXTENT};
+
+/// Presents several extent lists as one, without copying them.
+///
+/// The lists must each be GC-lists, and every extent of a list must
+/// start and end before every extent of the following list, as is
+/// the case for the posting lists of consecutive index segments. The
+/// merged view then behaves exactly like the concatenation of the
+/// lists.
+///
+/// Each access function consults a table of the first and last
+/// extents of every list to pick the one list that holds the answer.
+#[derive(Debug, Clone)]
+pub struct MergedLists<L> {
+    // Empty lists are discarded
+    lists: Vec<L>,
+    // The first and last extent of each list
+    bounds: Vec<(ValidExtent, ValidExtent)>,
+}
+
+impl<L> MergedLists<L>
+where
+    L: AsRef<[ValidExtent]>,
+{
+    pub fn new<I>(lists: I) -> Self
+    where
+        I: IntoIterator<Item = L>,
+    {
+        let mut merged = MergedLists {
+            lists: Vec::new(),
+            bounds: Vec::new(),
+        };
+
+        for list in lists {
+            let extents = list.as_ref();
+            let (first, last) = match (extents.first(), extents.last()) {
+                (Some(&first), Some(&last)) => (first, last),
+                _ => continue,
+            };
+
+            if let Some(&(_, previous)) = merged.bounds.last() {
+                debug_assert!(
+                    previous.0 < first.0 && previous.1 < first.1,
+                    "{:?} and {:?} are out of order",
+                    previous,
+                    first
+                );
+            }
+
+            merged.bounds.push((first, last));
+            merged.lists.push(list);
+        }
+
+        merged
+    }
+
+    /// The number of non-empty lists.
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    fn list(&self, idx: usize) -> &[ValidExtent] {
+        self.lists[idx].as_ref()
+    }
+}
+
+impl<L> Algebra for MergedLists<L>
+where
+    L: AsRef<[ValidExtent]>,
+{
+    fn tau(&self, k: Position) -> Extent {
+        // The first list with an extent starting at or after k
+        let idx = self
+            .bounds
+            .partition_point(|&(_, last)| Position::from(last.0) < k);
+        match idx {
+            idx if idx == self.len() => END_EXTENT,
+            idx => self.list(idx).tau(k),
+        }
+    }
+
+    fn tau_prime(&self, k: Position) -> Extent {
+        // The last list with an extent ending at or before k
+        let idx = self
+            .bounds
+            .partition_point(|&(first, _)| Position::from(first.1) <= k);
+        match idx {
+            0 => START_EXTENT,
+            idx => self.list(idx - 1).tau_prime(k),
+        }
+    }
+
+    fn rho(&self, k: Position) -> Extent {
+        // The first list with an extent ending at or after k
+        let idx = self
+            .bounds
+            .partition_point(|&(_, last)| Position::from(last.1) < k);
+        match idx {
+            idx if idx == self.len() => END_EXTENT,
+            idx => self.list(idx).rho(k),
+        }
+    }
+
+    fn rho_prime(&self, k: Position) -> Extent {
+        // The last list with an extent starting at or before k
+        let idx = self
+            .bounds
+            .partition_point(|&(first, _)| Position::from(first.0) <= k);
+        match idx {
+            0 => START_EXTENT,
+            idx => self.list(idx - 1).rho_prime(k),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    // Builds a GC-list from arbitrary gaps and widths
+    fn gc_list(raw: &[(u8, u8)]) -> Vec<ValidExtent> {
+        let mut extents: Vec<ValidExtent> = Vec::new();
+        for &(gap, width) in raw {
+            let start = extents.last().map_or(0, |e| e.0 + 1) + u64::from(gap);
+            let end = extents
+                .last()
+                .map_or(0, |e| e.1 + 1)
+                .max(start + u64::from(width));
+            extents.push((start, end));
+        }
+        extents
+    }
+
+    // Splits the list into consecutive pieces at arbitrary points
+    fn split<'a>(extents: &'a [ValidExtent], cuts: &[usize]) -> Vec<&'a [ValidExtent]> {
+        let mut points: Vec<_> = cuts.iter().map(|&c| c % (extents.len() + 1)).collect();
+        points.push(0);
+        points.push(extents.len());
+        points.sort_unstable();
+        points.dedup();
+
+        // Include some empty lists to be discarded
+        let mut lists = vec![&extents[..0]];
+        lists.extend(points.windows(2).map(|w| &extents[w[0]..w[1]]));
+        lists
+    }
+
+    #[test]
+    fn merged_lists_match_concatenated_list_at_any_k() {
+        fn prop(raw: Vec<(u8, u8)>, cuts: Vec<usize>, k: Position) -> bool {
+            let extents = gc_list(&raw);
+            let merged = MergedLists::new(split(&extents, &cuts));
+            let concatenated = &extents[..];
+
+            merged.tau(k) == concatenated.tau(k)
+                && merged.rho(k) == concatenated.rho(k)
+                && merged.tau_prime(k) == concatenated.tau_prime(k)
+                && merged.rho_prime(k) == concatenated.rho_prime(k)
+        }
+
+        quickcheck(prop as fn(_, _, _) -> _);
+    }
+
+    #[test]
+    fn merged_lists_all_tau_matches_concatenated_list() {
+        fn prop(raw: Vec<(u8, u8)>, cuts: Vec<usize>) -> bool {
+            let extents = gc_list(&raw);
+            let merged = MergedLists::new(split(&extents, &cuts));
+
+            (&merged).iter_tau().eq(extents.iter().copied())
+                && (&merged).iter_rho().eq(extents.iter().copied())
+                && (&merged).iter_tau_prime().eq(extents.iter().rev().copied())
+                && (&merged).iter_rho_prime().eq(extents.iter().rev().copied())
+        }
+
+        quickcheck(prop as fn(_, _) -> _);
+    }
+
+    #[test]
+    fn merged_lists_route_to_each_list() {
+        let a = [(1, 2), (3, 4)];
+        let b = [(10, 12), (11, 13)];
+        let merged = MergedLists::new(vec![&a[..], &[][..], &b[..]]);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged.tau(4.into()), (10, 12));
+        assert_eq!(merged.rho(4.into()), (3, 4));
+        assert_eq!(merged.tau_prime(12.into()), (10, 12));
+        assert_eq!(merged.rho_prime(10.into()), (10, 12));
+        assert_eq!(merged.rho_prime(9.into()), (3, 4));
+        assert_eq!(merged.tau(14.into()), END_EXTENT);
+        assert_eq!(merged.tau_prime(1.into()), START_EXTENT);
+    }
+
+    #[test]
+    fn merged_lists_of_nothing_are_empty() {
+        let merged = MergedLists::<&[ValidExtent]>::new(vec![]);
+
+        assert!(merged.is_empty());
+        assert_eq!(merged.tau(Position::NegativeInfinity), END_EXTENT);
+        assert_eq!(merged.rho_prime(Position::PositiveInfinity), START_EXTENT);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{Algebra, ContainedIn, Containing, NotContainedIn, NotContaining, ValidExtent};
+use std::cmp::Reverse;
+
+/// A set of regions that may contain each other, such as nested
+/// sections or list items.
+///
+/// A GC-list cannot hold two regions where one contains the other, so
+/// the regions are split into levels by their depth: the length of
+/// the longest chain of regions containing them. Every level is a
+/// GC-list and may be used with any operator.
+///
+/// The operators are not aware of the nesting, but the extreme levels
+/// often answer questions about regions at any depth. An extent is
+/// contained in some region exactly when it is contained in one of the
+/// [`outermost`](Self::outermost) regions, and it contains some region
+/// exactly when it contains one of the [`innermost`](Self::innermost)
+/// regions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "Vec<ValidExtent>", into = "Vec<ValidExtent>")
+)]
+pub struct NestedLayer {
+    levels: Vec<Vec<ValidExtent>>,
+}
+
+impl NestedLayer {
+    /// Splits the regions into levels. They may be given in any order
+    /// and duplicates are removed.
+    pub fn new<I>(extents: I) -> Self
+    where
+        I: IntoIterator<Item = ValidExtent>,
+    {
+        // Every region follows all of the regions containing it
+        let mut extents: Vec<_> = extents.into_iter().collect();
+        extents.sort_unstable_by_key(|&(start, end)| (start, Reverse(end)));
+        extents.dedup();
+
+        // The regions seen so far with an end at or after a given end
+        // contain any later region with that end
+        let mut ends: Vec<_> = extents.iter().map(|e| Reverse(e.1)).collect();
+        ends.sort_unstable();
+        ends.dedup();
+        let mut deepest_containing = PrefixMax::new(ends.len());
+
+        let mut levels: Vec<Vec<ValidExtent>> = Vec::new();
+        for extent in extents {
+            let idx = ends
+                .binary_search(&Reverse(extent.1))
+                .expect("Every end was recorded");
+            let depth = deepest_containing.max(idx);
+            deepest_containing.raise(idx, depth + 1);
+
+            if depth == levels.len() {
+                levels.push(Vec::new());
+            }
+            levels[depth].push(extent);
+        }
+
+        NestedLayer { levels }
+    }
+
+    /// The number of levels.
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// The regions at the depth, contained in exactly `depth` others
+    /// along their longest chain of containing regions.
+    pub fn level(&self, depth: usize) -> &[ValidExtent] {
+        self.levels.get(depth).map_or(&[], |l| l)
+    }
+
+    /// Every level, from the outermost.
+    pub fn levels(&self) -> &[Vec<ValidExtent>] {
+        &self.levels
+    }
+
+    /// The number of regions.
+    pub fn len(&self) -> usize {
+        self.levels.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Every region at any depth, ordered by start with containing
+    /// regions first. This is not a GC-list.
+    pub fn all(&self) -> Vec<ValidExtent> {
+        let mut all: Vec<_> = self.levels.concat();
+        all.sort_unstable_by_key(|&(start, end)| (start, Reverse(end)));
+        all
+    }
+
+    /// The regions not contained in any other region.
+    pub fn outermost(&self) -> &[ValidExtent] {
+        self.level(0)
+    }
+
+    /// The regions not containing any other region.
+    pub fn innermost(&self) -> Vec<ValidExtent> {
+        innermost(self.levels.concat())
+    }
+
+    /// The regions, at any depth, containing an extent of `a`.
+    pub fn containing<A: Algebra>(&self, a: A) -> Self {
+        self.filter(|level| Containing::new(level, &a).iter_tau().collect())
+    }
+
+    /// The regions, at any depth, contained in an extent of `a`.
+    pub fn contained_in<A: Algebra>(&self, a: A) -> Self {
+        self.filter(|level| ContainedIn::new(level, &a).iter_tau().collect())
+    }
+
+    /// The regions, at any depth, not containing an extent of `a`.
+    pub fn not_containing<A: Algebra>(&self, a: A) -> Self {
+        self.filter(|level| NotContaining::new(level, &a).iter_tau().collect())
+    }
+
+    /// The regions, at any depth, not contained in an extent of `a`.
+    pub fn not_contained_in<A: Algebra>(&self, a: A) -> Self {
+        self.filter(|level| NotContainedIn::new(level, &a).iter_tau().collect())
+    }
+
+    // Regions may move to a shallower level once the regions
+    // containing them are removed
+    fn filter<F>(&self, mut keep: F) -> Self
+    where
+        F: FnMut(&[ValidExtent]) -> Vec<ValidExtent>,
+    {
+        Self::new(self.levels.iter().flat_map(|level| keep(level)))
+    }
+}
+
+impl From<Vec<ValidExtent>> for NestedLayer {
+    fn from(extents: Vec<ValidExtent>) -> Self {
+        Self::new(extents)
+    }
+}
+
+impl From<NestedLayer> for Vec<ValidExtent> {
+    fn from(nested: NestedLayer) -> Self {
+        nested.all()
+    }
+}
+
+// The extents that contain no other extent, in order
+fn innermost(mut extents: Vec<ValidExtent>) -> Vec<ValidExtent> {
+    extents.sort_unstable_by_key(|&(start, end)| (end, Reverse(start)));
+
+    let mut kept: Vec<ValidExtent> = Vec::with_capacity(extents.len());
+    for extent in extents {
+        if kept.last().is_none_or(|last| last.0 < extent.0) {
+            kept.push(extent);
+        }
+    }
+    kept
+}
+
+// A Fenwick tree answering the maximum of a prefix
+struct PrefixMax(Vec<usize>);
+
+impl PrefixMax {
+    fn new(len: usize) -> Self {
+        PrefixMax(vec![0; len + 1])
+    }
+
+    // The largest value at or before the index
+    fn max(&self, idx: usize) -> usize {
+        let mut i = idx + 1;
+        let mut max = 0;
+        while i > 0 {
+            max = max.max(self.0[i]);
+            i &= i - 1;
+        }
+        max
+    }
+
+    fn raise(&mut self, idx: usize, value: usize) {
+        let mut i = idx + 1;
+        while i < self.0.len() {
+            self.0[i] = self.0[i].max(value);
+            i += i & i.wrapping_neg();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::quickcheck;
+
+    fn contains(a: ValidExtent, b: ValidExtent) -> bool {
+        a != b && a.0 <= b.0 && b.1 <= a.1
+    }
+
+    fn arbitrary_extents(raw: &[(u8, u8)]) -> Vec<ValidExtent> {
+        raw.iter()
+            .map(|&(start, len)| (u64::from(start), u64::from(start) + u64::from(len % 32)))
+            .collect()
+    }
+
+    // Sections with nested subsections, and the word at 25
+    fn sections() -> NestedLayer {
+        NestedLayer::new(vec![(60, 90), (20, 30), (0, 100), (10, 50)])
+    }
+
+    #[test]
+    fn regions_are_split_by_depth() {
+        let nested = sections();
+
+        assert_eq!(nested.depth(), 3);
+        assert_eq!(nested.level(0), [(0, 100)]);
+        assert_eq!(nested.level(1), [(10, 50), (60, 90)]);
+        assert_eq!(nested.level(2), [(20, 30)]);
+        assert_eq!(nested.level(3), []);
+        assert_eq!(nested.len(), 4);
+    }
+
+    #[test]
+    fn regions_are_found_at_any_depth() {
+        let word = [(25, 26)];
+        let found = sections().containing(&word[..]);
+
+        assert_eq!(found.all(), [(0, 100), (10, 50), (20, 30)]);
+        assert_eq!(found.outermost(), [(0, 100)]);
+        assert_eq!(found.innermost(), [(20, 30)]);
+    }
+
+    #[test]
+    fn flat_layer_cannot_hold_nested_regions() {
+        // Treated as a GC-list, the nested regions give a wrong answer
+        let flat = sections().all();
+        let word = [(25, 26)];
+        let wrong: Vec<_> = Containing::new(&flat[..], &word[..]).iter_tau().collect();
+
+        assert_ne!(wrong, sections().containing(&word[..]).all());
+    }
+
+    #[test]
+    fn extreme_levels_stand_in_for_any_depth() {
+        let nested = sections();
+        let words = [(5, 6), (25, 26), (55, 56), (95, 96)];
+
+        let in_any: Vec<_> = words
+            .iter()
+            .copied()
+            .filter(|&w| nested.all().iter().any(|&s| contains(s, w)))
+            .collect();
+        let in_outermost: Vec<_> = ContainedIn::new(&words[..], nested.outermost())
+            .iter_tau()
+            .collect();
+
+        assert_eq!(in_outermost, in_any);
+        assert_eq!(
+            nested.contained_in(&[(10, 50)][..]).all(),
+            [(10, 50), (20, 30)]
+        );
+        assert_eq!(
+            nested.not_contained_in(&[(10, 50)][..]).all(),
+            [(0, 100), (60, 90)]
+        );
+        assert_eq!(nested.not_containing(&[(25, 26)][..]).all(), [(60, 90)]);
+    }
+
+    #[test]
+    fn crossing_regions_share_a_level() {
+        let nested = NestedLayer::new(vec![(0, 10), (5, 15), (6, 8), (6, 8)]);
+
+        assert_eq!(nested.level(0), [(0, 10), (5, 15)]);
+        assert_eq!(nested.level(1), [(6, 8)]);
+    }
+
+    #[test]
+    fn every_level_is_a_gc_list() {
+        fn prop(raw: Vec<(u8, u8)>) -> bool {
+            let nested = NestedLayer::new(arbitrary_extents(&raw));
+
+            nested
+                .levels()
+                .iter()
+                .all(|l| l.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1))
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+
+    #[test]
+    fn extreme_levels_match_definitions() {
+        fn prop(raw: Vec<(u8, u8)>) -> bool {
+            let extents = arbitrary_extents(&raw);
+            let nested = NestedLayer::new(extents.clone());
+            let all = nested.all();
+
+            let outermost: Vec<_> = all
+                .iter()
+                .copied()
+                .filter(|&e| !all.iter().any(|&o| contains(o, e)))
+                .collect();
+            let innermost: Vec<_> = all
+                .iter()
+                .copied()
+                .filter(|&e| !all.iter().any(|&i| contains(e, i)))
+                .collect();
+
+            let mut unique = extents;
+            unique.sort_unstable();
+            unique.dedup();
+            let mut found = all.clone();
+            found.sort_unstable();
+
+            found == unique
+                && nested.outermost() == &outermost[..]
+                && nested.innermost() == innermost
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+
+    #[test]
+    fn containing_matches_definition() {
+        fn prop(raw: Vec<(u8, u8)>, words: Vec<u8>) -> bool {
+            let nested = NestedLayer::new(arbitrary_extents(&raw));
+            let mut words: Vec<_> = words
+                .iter()
+                .map(|&w| (u64::from(w), u64::from(w)))
+                .collect();
+            words.sort_unstable();
+            words.dedup();
+
+            let expected: Vec<_> = nested
+                .all()
+                .into_iter()
+                .filter(|&r| words.iter().any(|&w| r.0 <= w.0 && w.1 <= r.1))
+                .collect();
+
+            nested.containing(&words[..]).all() == expected
+        }
+
+        quickcheck(prop as fn(_, _) -> _);
+    }
+}
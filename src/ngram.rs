@@ -0,0 +1,210 @@
+use crate::{Algebra, Index, Position, ValidExtent};
+
+/// An index of every run of `n` characters of a text, used to find
+/// substrings that do not line up with terms, such as `ackage` inside
+/// `package`.
+///
+/// The extents are byte offsets into the text, with an exclusive end,
+/// like those produced by the tokenizers, so the results of
+/// [`find`](Self::find) can be combined with any other extent list.
+#[derive(Debug, Clone)]
+pub struct NgramIndex {
+    n: usize,
+    text: String,
+    grams: Index,
+}
+
+impl NgramIndex {
+    /// Indexes every n-gram of the text.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is zero.
+    pub fn new(text: impl Into<String>, n: usize) -> Self {
+        assert!(n > 0, "n-grams must have at least one character");
+
+        let text = text.into();
+        let mut grams = Index::new();
+
+        let boundaries: Vec<_> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(Some(text.len()))
+            .collect();
+        for w in boundaries.windows(n + 1) {
+            let (start, end) = (w[0], w[n]);
+            grams.extend_term(&text[start..end], Some((start as u64, end as u64)));
+        }
+
+        NgramIndex { n, text, grams }
+    }
+
+    /// Indexes every run of three characters of the text.
+    pub fn trigrams(text: impl Into<String>) -> Self {
+        Self::new(text, 3)
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The occurrences of one n-gram.
+    pub fn gram(&self, gram: &str) -> &[ValidExtent] {
+        self.grams.term(gram)
+    }
+
+    /// Finds every occurrence of the substring, including those that
+    /// overlap each other.
+    ///
+    /// The substring is covered by n-grams at fixed offsets. Each
+    /// occurrence of the rarest of them is a candidate, which is kept
+    /// only if every other n-gram occurs at the same relative offset
+    /// and the text there matches the substring. Substrings shorter
+    /// than `n` characters are found by scanning the text instead.
+    pub fn find(&self, substring: &str) -> Vec<ValidExtent> {
+        let boundaries: Vec<_> = substring
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(Some(substring.len()))
+            .collect();
+        let chars = boundaries.len() - 1;
+
+        if chars == 0 {
+            return Vec::new();
+        }
+        if chars < self.n {
+            return self.scan(substring);
+        }
+
+        // Every n-th n-gram, plus the last so that the end is covered
+        let mut starts: Vec<_> = (0..=chars - self.n).step_by(self.n).collect();
+        if starts.last() != Some(&(chars - self.n)) {
+            starts.push(chars - self.n);
+        }
+
+        let grams: Vec<(u64, &[ValidExtent])> = starts
+            .iter()
+            .map(|&c| {
+                let (start, end) = (boundaries[c], boundaries[c + self.n]);
+                (start as u64, self.gram(&substring[start..end]))
+            })
+            .collect();
+
+        let &(rarest_offset, rarest) = grams
+            .iter()
+            .min_by_key(|(_, list)| list.len())
+            .expect("A substring has at least one n-gram");
+
+        let len = substring.len() as u64;
+        rarest
+            .iter()
+            .filter_map(|&(p, _)| p.checked_sub(rarest_offset))
+            .filter(|&start| {
+                grams.iter().all(|&(offset, list)| {
+                    let k = start + offset;
+                    list.tau(k.into()).0 == Position::Valid(k)
+                })
+            })
+            .filter(|&start| self.matches_at(start as usize, substring))
+            .map(|start| (start, start + len))
+            .collect()
+    }
+
+    fn scan(&self, substring: &str) -> Vec<ValidExtent> {
+        let len = substring.len() as u64;
+        self.text
+            .char_indices()
+            .map(|(i, _)| i)
+            .filter(|&i| self.matches_at(i, substring))
+            .map(|i| (i as u64, i as u64 + len))
+            .collect()
+    }
+
+    fn matches_at(&self, start: usize, substring: &str) -> bool {
+        self.text
+            .get(start..)
+            .is_some_and(|rest| rest.starts_with(substring))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ContainedIn;
+    use quickcheck::quickcheck;
+
+    // Every occurrence, found the slow way
+    fn naive(text: &str, substring: &str) -> Vec<ValidExtent> {
+        if substring.is_empty() {
+            return Vec::new();
+        }
+        let len = substring.len() as u64;
+        (0..text.len())
+            .filter(|&i| text.is_char_boundary(i) && text[i..].starts_with(substring))
+            .map(|i| (i as u64, i as u64 + len))
+            .collect()
+    }
+
+    // Text over a small alphabet so that substrings repeat
+    fn small_alphabet(raw: &[u8]) -> String {
+        raw.iter()
+            .map(|b| ['a', 'b', 'é'][*b as usize % 3])
+            .collect()
+    }
+
+    #[test]
+    fn substrings_are_found_inside_words() {
+        let ngrams = NgramIndex::trigrams("The package has packages, not a backpack.");
+
+        assert_eq!(ngrams.find("ackage"), [(5, 11), (17, 23)]);
+        assert_eq!(ngrams.find("pack"), [(4, 8), (16, 20), (36, 40)]);
+        assert_eq!(ngrams.find("parcel"), []);
+    }
+
+    #[test]
+    fn short_substrings_are_scanned() {
+        let ngrams = NgramIndex::trigrams("Part AB-12 and AB-123");
+
+        assert_eq!(ngrams.find("AB"), [(5, 7), (15, 17)]);
+        assert_eq!(ngrams.find(""), []);
+    }
+
+    #[test]
+    fn overlapping_occurrences_are_all_found() {
+        let ngrams = NgramIndex::new("aaaaa", 2);
+
+        assert_eq!(ngrams.find("aaa"), [(0, 3), (1, 4), (2, 5)]);
+    }
+
+    #[test]
+    fn substrings_combine_with_other_extents() {
+        let text = "Order AB-123 today. Part AB-124 is late.";
+        let ngrams = NgramIndex::trigrams(text);
+        let late_sentence = [(20, 40)];
+
+        let found = ngrams.find("AB-12");
+        let late: Vec<_> = ContainedIn::new(&found[..], &late_sentence[..])
+            .iter_tau()
+            .collect();
+
+        assert_eq!(found, [(6, 11), (25, 30)]);
+        assert_eq!(late, [(25, 30)]);
+    }
+
+    #[test]
+    fn find_matches_naive_search() {
+        fn prop(text: Vec<u8>, substring: Vec<u8>, n: u8) -> bool {
+            let text = small_alphabet(&text);
+            let substring = small_alphabet(&substring[..substring.len().min(8)]);
+            let ngrams = NgramIndex::new(text.as_str(), usize::from(n % 4) + 1);
+
+            ngrams.find(&substring) == naive(&text, &substring)
+        }
+
+        quickcheck(prop as fn(_, _, _) -> _);
+    }
+}
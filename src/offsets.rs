@@ -0,0 +1,282 @@
+use crate::ValidExtent;
+use std::{error::Error, fmt};
+
+/// The unit in which offsets into a text are counted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OffsetUnit {
+    /// Bytes of UTF-8, as used by Rust strings and the tokenizers
+    Byte,
+    /// Unicode code points, as used by Python strings
+    Char,
+    /// UTF-16 code units, as used by JavaScript strings
+    Utf16,
+}
+
+impl OffsetUnit {
+    fn idx(self) -> usize {
+        self as usize
+    }
+}
+
+/// A reason that an offset could not be converted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OffsetError {
+    /// The offset is past the end of the text.
+    OutOfBounds {
+        offset: u64,
+        unit: OffsetUnit,
+        len: u64,
+    },
+    /// The offset is in the middle of a character.
+    NotCharBoundary { offset: u64, unit: OffsetUnit },
+}
+
+impl fmt::Display for OffsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffsetError::OutOfBounds { offset, unit, len } => write!(
+                f,
+                "{:?} offset {} is past the end of the text of length {}",
+                unit, offset, len
+            ),
+            OffsetError::NotCharBoundary { offset, unit } => write!(
+                f,
+                "{:?} offset {} is in the middle of a character",
+                unit, offset
+            ),
+        }
+    }
+}
+
+impl Error for OffsetError {}
+
+// A character that is wider than one unit in some encoding. Both
+// arrays are indexed by `OffsetUnit`.
+#[derive(Debug, Copy, Clone)]
+struct Wide {
+    start: [u64; 3],
+    width: [u64; 3],
+}
+
+/// Converts offsets into one text between bytes, characters and
+/// UTF-16 code units.
+///
+/// Only the characters outside of ASCII are recorded, as every unit
+/// agrees on the width of the others. Converting an offset costs a
+/// binary search over those characters.
+#[derive(Debug, Clone)]
+pub struct OffsetMap {
+    wide: Vec<Wide>,
+    len: [u64; 3],
+}
+
+impl OffsetMap {
+    pub fn new(text: &str) -> Self {
+        let mut wide = Vec::new();
+        let mut position = [0; 3];
+
+        for c in text.chars() {
+            let width = [c.len_utf8() as u64, 1, c.len_utf16() as u64];
+            if !c.is_ascii() {
+                wide.push(Wide {
+                    start: position,
+                    width,
+                });
+            }
+            for (p, w) in position.iter_mut().zip(&width) {
+                *p += w;
+            }
+        }
+
+        OffsetMap {
+            wide,
+            len: position,
+        }
+    }
+
+    /// The length of the text in the unit.
+    pub fn len(&self, unit: OffsetUnit) -> u64 {
+        self.len[unit.idx()]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len[0] == 0
+    }
+
+    /// Converts an offset between units. The offset must lie on a
+    /// character boundary.
+    pub fn convert(
+        &self,
+        offset: u64,
+        from: OffsetUnit,
+        to: OffsetUnit,
+    ) -> Result<u64, OffsetError> {
+        let (f, t) = (from.idx(), to.idx());
+
+        if offset > self.len[f] {
+            return Err(OffsetError::OutOfBounds {
+                offset,
+                unit: from,
+                len: self.len[f],
+            });
+        }
+
+        // The last wide character starting at or before the offset;
+        // everything between it and the offset is ASCII.
+        let idx = self.wide.partition_point(|w| w.start[f] <= offset);
+        let w = match idx {
+            0 => return Ok(offset),
+            idx => self.wide[idx - 1],
+        };
+
+        let into = offset - w.start[f];
+        if into == 0 {
+            Ok(w.start[t])
+        } else if into < w.width[f] {
+            Err(OffsetError::NotCharBoundary { offset, unit: from })
+        } else {
+            Ok(w.start[t] + w.width[t] + (into - w.width[f]))
+        }
+    }
+
+    /// Converts both ends of an extent between units. The extent is
+    /// treated as half-open, so its end is also a character boundary.
+    pub fn convert_extent(
+        &self,
+        extent: ValidExtent,
+        from: OffsetUnit,
+        to: OffsetUnit,
+    ) -> Result<ValidExtent, OffsetError> {
+        Ok((
+            self.convert(extent.0, from, to)?,
+            self.convert(extent.1, from, to)?,
+        ))
+    }
+
+    /// Converts every extent of a list, such as a layer, between
+    /// units, failing on the first extent that cannot be converted.
+    pub fn convert_extents<I>(
+        &self,
+        extents: I,
+        from: OffsetUnit,
+        to: OffsetUnit,
+    ) -> Result<Vec<ValidExtent>, OffsetError>
+    where
+        I: IntoIterator<Item = ValidExtent>,
+    {
+        extents
+            .into_iter()
+            .map(|e| self.convert_extent(e, from, to))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck::quickcheck;
+    use OffsetUnit::*;
+
+    // The offset of every character boundary, in each unit
+    fn boundaries(text: &str) -> Vec<[u64; 3]> {
+        let mut position = [0; 3];
+        let mut all = vec![position];
+        for c in text.chars() {
+            position[0] += c.len_utf8() as u64;
+            position[1] += 1;
+            position[2] += c.len_utf16() as u64;
+            all.push(position);
+        }
+        all
+    }
+
+    #[test]
+    fn offsets_convert_between_all_units() {
+        // é is 2 bytes, 😀 is 4 bytes and 2 UTF-16 code units
+        let text = "aé😀b";
+        let map = OffsetMap::new(text);
+
+        assert_eq!(map.len(Byte), 8);
+        assert_eq!(map.len(Char), 4);
+        assert_eq!(map.len(Utf16), 5);
+
+        assert_eq!(map.convert(3, Byte, Char), Ok(2));
+        assert_eq!(map.convert(7, Byte, Utf16), Ok(4));
+        assert_eq!(map.convert(3, Char, Byte), Ok(7));
+        assert_eq!(map.convert(4, Utf16, Char), Ok(3));
+        assert_eq!(map.convert(5, Utf16, Byte), Ok(8));
+        assert_eq!(map.convert_extent((1, 3), Char, Byte), Ok((1, 7)));
+        assert_eq!(&text[1..7], "é😀");
+    }
+
+    #[test]
+    fn offsets_inside_characters_are_rejected() {
+        let map = OffsetMap::new("aé😀b");
+
+        assert_eq!(
+            map.convert(2, Byte, Char),
+            Err(OffsetError::NotCharBoundary {
+                offset: 2,
+                unit: Byte
+            })
+        );
+        assert_eq!(
+            map.convert(3, Utf16, Byte),
+            Err(OffsetError::NotCharBoundary {
+                offset: 3,
+                unit: Utf16
+            })
+        );
+        assert_eq!(
+            map.convert(5, Char, Byte),
+            Err(OffsetError::OutOfBounds {
+                offset: 5,
+                unit: Char,
+                len: 4
+            })
+        );
+    }
+
+    #[test]
+    fn ascii_offsets_are_unchanged() {
+        let map = OffsetMap::new("Hello, world!");
+
+        for offset in 0..=13 {
+            assert_eq!(map.convert(offset, Char, Utf16), Ok(offset));
+            assert_eq!(map.convert(offset, Utf16, Byte), Ok(offset));
+        }
+    }
+
+    #[test]
+    fn every_boundary_converts_in_both_directions() {
+        fn prop(text: String) -> bool {
+            let map = OffsetMap::new(&text);
+            let units = [Byte, Char, Utf16];
+
+            boundaries(&text).iter().all(|position| {
+                units.iter().all(|&from| {
+                    units.iter().all(|&to| {
+                        map.convert(position[from.idx()], from, to) == Ok(position[to.idx()])
+                    })
+                })
+            })
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+
+    #[test]
+    fn byte_conversion_fails_exactly_off_boundaries() {
+        fn prop(text: String) -> bool {
+            let map = OffsetMap::new(&text);
+
+            (0..=text.len() as u64).all(|offset| {
+                map.convert(offset, Byte, Char).is_ok() == text.is_char_boundary(offset as usize)
+            })
+        }
+
+        quickcheck(prop as fn(_) -> _);
+    }
+}
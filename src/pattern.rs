@@ -0,0 +1,365 @@
+use crate::{doc_and_offset_to_k, NgramIndex, ValidExtent};
+use regex_automata::{
+    hybrid::dfa::{OverlappingState, DFA},
+    meta::Regex,
+    nfa::thompson,
+    Anchored, Input, MatchError, MatchKind,
+};
+use regex_syntax::hir::{literal::Extractor, Hir};
+use std::{convert::TryFrom, error::Error, fmt};
+
+/// A reason that a [`RegexSearch`] could not be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegexError {
+    /// The pattern is not a valid regular expression.
+    Invalid(String),
+    /// The pattern can match without covering any text, such as `a*`
+    /// or `\b`.
+    MatchesEmpty,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexError::Invalid(e) => write!(f, "invalid regular expression: {}", e),
+            RegexError::MatchesEmpty => "the regular expression can match the empty string".fmt(f),
+        }
+    }
+}
+
+impl Error for RegexError {}
+
+/// Finds the text matching a regular expression, as a GC-list of byte
+/// extents with an exclusive end.
+///
+/// Matches may overlap or nest, so only the minimal ones are kept: a
+/// match is dropped if it contains another match. For `a.*b` in
+/// `aab`, the result is the one extent covering `ab`.
+#[derive(Debug, Clone)]
+pub struct RegexSearch {
+    regex: Regex,
+    // Reports the end of every match, overlapping or not
+    forward: DFA,
+    // Finds the start of a match from its end
+    reverse: DFA,
+    // Every match begins with one of these, if they are known
+    prefixes: Option<Vec<String>>,
+}
+
+impl RegexSearch {
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let hir = regex_syntax::parse(pattern).map_err(|e| RegexError::Invalid(e.to_string()))?;
+
+        if hir.properties().minimum_len() == Some(0) {
+            return Err(RegexError::MatchesEmpty);
+        }
+
+        let regex = Regex::builder()
+            .build_from_hir(&hir)
+            .map_err(|e| RegexError::Invalid(e.to_string()))?;
+        let forward = lazy_dfa(&hir, false)?;
+        let reverse = lazy_dfa(&hir, true)?;
+
+        let prefixes = Extractor::new().extract(&hir).literals().and_then(|lits| {
+            lits.iter()
+                .map(|l| match std::str::from_utf8(l.as_bytes()) {
+                    Ok(l) if !l.is_empty() => Some(l.to_string()),
+                    _ => None,
+                })
+                .collect()
+        });
+
+        Ok(RegexSearch {
+            regex,
+            forward,
+            reverse,
+            prefixes,
+        })
+    }
+
+    /// Finds the minimal matches in the text.
+    ///
+    /// The text is scanned once for the end of every match, and the
+    /// start of the shortest match ending there is found by searching
+    /// backwards. Should the lazy DFA used for that give up, such as
+    /// on a Unicode word boundary next to non-ASCII text, a match is
+    /// instead tried from every character, which takes time quadratic
+    /// in the length of the text.
+    pub fn find(&self, text: &str) -> Vec<ValidExtent> {
+        self.scan(text).unwrap_or_else(|_| {
+            let starts = (0..text.len()).filter(|&i| text.is_char_boundary(i));
+            self.minimal_matches(text, starts)
+        })
+    }
+
+    /// Finds the minimal matches in the text of the n-gram index.
+    ///
+    /// When every match must begin with one of a small set of literal
+    /// strings, only the places where the index finds those strings
+    /// are tried. Otherwise the whole text is scanned, as in
+    /// [`find`](Self::find).
+    pub fn find_indexed(&self, ngrams: &NgramIndex) -> Vec<ValidExtent> {
+        let prefixes = match &self.prefixes {
+            Some(prefixes) => prefixes,
+            None => return self.find(ngrams.text()),
+        };
+
+        let mut starts: Vec<_> = prefixes
+            .iter()
+            .flat_map(|p| ngrams.find(p))
+            .map(|(start, _)| start as usize)
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+
+        self.minimal_matches(ngrams.text(), starts)
+    }
+
+    /// Finds the minimal matches in each document, addressed by the
+    /// position of the document in the iterator.
+    ///
+    /// Documents without any match are skipped after one quick check
+    /// that uses the literal prefilters of the regex engine. A match
+    /// that ends more than `u32::MAX` bytes into its document cannot
+    /// be addressed and is dropped.
+    pub fn find_in_documents<I, T>(&self, documents: I) -> Vec<ValidExtent>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut extents = Vec::new();
+        for (doc, text) in (0..).zip(documents) {
+            let text = text.as_ref();
+            if !self.regex.is_match(text) {
+                continue;
+            }
+
+            extents.extend(self.find(text).into_iter().map_while(|(start, end)| {
+                Some((
+                    doc_and_offset_to_k(doc, u32::try_from(start).ok()?),
+                    doc_and_offset_to_k(doc, u32::try_from(end).ok()?),
+                ))
+            }));
+        }
+        extents
+    }
+
+    // Pairs the end of every match with the start of the shortest
+    // match ending there. That match is minimal unless it contains one
+    // ending earlier, which would have to start no earlier than it.
+    fn scan(&self, text: &str) -> Result<Vec<ValidExtent>, MatchError> {
+        let mut forward_cache = self.forward.create_cache();
+        let mut reverse_cache = self.reverse.create_cache();
+        let mut state = OverlappingState::start();
+        let input = Input::new(text);
+
+        let mut minimal = Vec::new();
+        let mut latest_start = None;
+        loop {
+            self.forward
+                .try_search_overlapping_fwd(&mut forward_cache, &input, &mut state)?;
+            let end = match state.get_match() {
+                Some(m) => m.offset(),
+                None => return Ok(minimal),
+            };
+
+            let backwards = Input::new(text)
+                .range(..end)
+                .anchored(Anchored::Yes)
+                .earliest(true);
+            let start = match self
+                .reverse
+                .try_search_rev(&mut reverse_cache, &backwards)?
+            {
+                Some(m) => m.offset(),
+                None => continue,
+            };
+
+            if latest_start.is_none_or(|latest| start > latest) {
+                minimal.push((start as u64, end as u64));
+                latest_start = Some(start);
+            }
+        }
+    }
+
+    // The end of the shortest match beginning exactly at `start`
+    fn shortest_from(&self, text: &str, start: usize) -> Option<usize> {
+        let input = Input::new(text)
+            .range(start..)
+            .anchored(Anchored::Yes)
+            .earliest(true);
+        self.regex.search_half(&input).map(|m| m.offset())
+    }
+
+    // Keeps the shortest match at each start that contains no match
+    // beginning at a later start. `starts` must be increasing.
+    fn minimal_matches<I>(&self, text: &str, starts: I) -> Vec<ValidExtent>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let shortest: Vec<_> = starts
+            .into_iter()
+            .filter_map(|start| Some((start as u64, self.shortest_from(text, start)? as u64)))
+            .collect();
+
+        let mut minimal = Vec::new();
+        let mut earliest_later_end = u64::MAX;
+        for &(start, end) in shortest.iter().rev() {
+            if end < earliest_later_end {
+                minimal.push((start, end));
+                earliest_later_end = end;
+            }
+        }
+        minimal.reverse();
+        minimal
+    }
+}
+
+// A lazy DFA reporting every match, for searching forwards or, with a
+// reversed pattern, backwards
+fn lazy_dfa(hir: &Hir, reverse: bool) -> Result<DFA, RegexError> {
+    let nfa = thompson::Compiler::new()
+        .configure(
+            thompson::Config::new()
+                .reverse(reverse)
+                .which_captures(thompson::WhichCaptures::None),
+        )
+        .build_from_hir(hir)
+        .map_err(|e| RegexError::Invalid(e.to_string()))?;
+
+    DFA::builder()
+        .configure(
+            DFA::config()
+                .match_kind(MatchKind::All)
+                .unicode_word_boundary(true),
+        )
+        .build_from_nfa(nfa)
+        .map_err(|e| RegexError::Invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Algebra, ContainedIn, Documents};
+    use quickcheck::quickcheck;
+
+    fn search(pattern: &str) -> RegexSearch {
+        RegexSearch::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn matches_are_byte_extents() {
+        let text = "Call 555-1234 or 555-9876.";
+
+        let found = search(r"\d{3}-\d{4}").find(text);
+
+        assert_eq!(found, [(5, 13), (17, 25)]);
+        assert_eq!(&text[5..13], "555-1234");
+    }
+
+    #[test]
+    fn only_minimal_matches_are_kept() {
+        assert_eq!(search("a.*b").find("aab"), [(1, 3)]);
+        assert_eq!(search("a.*b").find("ab ab"), [(0, 2), (3, 5)]);
+        assert_eq!(search("aa").find("aaaa"), [(0, 2), (1, 3), (2, 4)]);
+        assert_eq!(search("é+").find("éé"), [(0, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn matches_see_the_surrounding_text() {
+        assert_eq!(search(r"\bcat\b").find("concat cat cats"), [(7, 10)]);
+        assert_eq!(search(r"\bcafé\b").find("un café noir"), [(3, 8)]);
+    }
+
+    #[test]
+    fn patterns_matching_nothing_are_rejected() {
+        assert_eq!(
+            RegexSearch::new("a*").unwrap_err(),
+            RegexError::MatchesEmpty
+        );
+        assert_eq!(
+            RegexSearch::new(r"\b").unwrap_err(),
+            RegexError::MatchesEmpty
+        );
+        assert!(matches!(
+            RegexSearch::new("(unclosed"),
+            Err(RegexError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn matches_combine_with_layers() {
+        let text = "Call 555-1234. Do not call 555-9876.";
+        let sentences = [(0, 14), (15, 36)];
+        let phone = search(r"\d{3}-\d{4}").find(text);
+
+        let found: Vec<_> = ContainedIn::new(&phone, &sentences[1..])
+            .iter_tau()
+            .collect();
+
+        assert_eq!(found, [(27, 35)]);
+    }
+
+    #[test]
+    fn matches_are_addressed_by_document() {
+        let docs = ["no numbers", "call 555-1234", "none", "555-0000"];
+
+        let found = search(r"\d{3}-\d{4}").find_in_documents(docs);
+        let per_document: Vec<_> = ContainedIn::new(&found, Documents::new(4))
+            .iter_tau()
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                (doc_and_offset_to_k(1, 5), doc_and_offset_to_k(1, 13)),
+                (doc_and_offset_to_k(3, 0), doc_and_offset_to_k(3, 8)),
+            ]
+        );
+        assert_eq!(per_document, found);
+    }
+
+    #[test]
+    fn indexed_search_matches_scan() {
+        fn prop(raw: Vec<u8>, pattern: u8) -> bool {
+            let patterns = ["ab", "a.b", "(ab|ba)+", "b[ab]a", "é", r"a\w*b", "[ab]é"];
+            let text: String = raw
+                .iter()
+                .map(|b| ['a', 'b', 'é', ' '][*b as usize % 4])
+                .collect();
+            let search = search(patterns[pattern as usize % patterns.len()]);
+
+            search.find_indexed(&NgramIndex::trigrams(text.as_str())) == search.find(&text)
+        }
+
+        quickcheck(prop as fn(_, _) -> _);
+    }
+
+    #[test]
+    fn scan_matches_trying_every_start() {
+        fn prop(raw: Vec<u8>, pattern: u8) -> bool {
+            let patterns = [
+                "ab", "a.*b", "(ab|ba)+", "a|ab|b", r"a\w*b", "é+b?", "[ab]{2}",
+            ];
+            let text: String = raw
+                .iter()
+                .map(|b| ['a', 'b', 'é', ' '][*b as usize % 4])
+                .collect();
+            let search = search(patterns[pattern as usize % patterns.len()]);
+            let starts = (0..text.len()).filter(|&i| text.is_char_boundary(i));
+
+            search.scan(&text).unwrap() == search.minimal_matches(&text, starts)
+        }
+
+        quickcheck(prop as fn(_, _) -> _);
+    }
+
+    #[test]
+    fn literal_prefixes_are_extracted() {
+        assert_eq!(
+            search("(foo|bar)baz").prefixes,
+            Some(vec!["foobaz".to_string(), "barbaz".to_string()])
+        );
+        assert_eq!(search(r"\w+").prefixes, None);
+    }
+}
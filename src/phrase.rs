@@ -0,0 +1,209 @@
+use crate::{Algebra, Epsilon, Extent, Position, END_EXTENT, START_EXTENT};
+
+/// Creates extents covering consecutive tokens, one from each of the
+/// term lists in order, such as the words of a phrase.
+///
+/// Unlike [`FollowedBy`](crate::FollowedBy), no other token may come
+/// between two terms of the phrase. The token list holds every token
+/// of the text, whatever its term, so that the token following each
+/// term can be found; [`Tokenizer::index`](crate::Tokenizer::index)
+/// records it as the [`TOKEN_LAYER`](crate::TOKEN_LAYER). This works
+/// for byte extents with an exclusive end, where consecutive tokens
+/// may touch, as well as for token positions.
+///
+/// Every extent of the phrase covers the same number of tokens, so
+/// the result is a GC-list.
+#[derive(Debug, Clone)]
+pub struct Phrase<A, T> {
+    terms: Vec<A>,
+    tokens: T,
+}
+
+impl<A, T> Phrase<A, T>
+where
+    A: Algebra,
+    T: Algebra,
+{
+    pub fn new(terms: Vec<A>, tokens: T) -> Self {
+        Phrase { terms, tokens }
+    }
+
+    // The last token of the phrase whose first token is given, if the
+    // rest of the phrase follows it
+    fn rest_after(&self, first: Extent) -> Option<Extent> {
+        self.terms[1..].iter().try_fold(first, |previous, term| {
+            let next = self.tokens.rho(previous.1.increment());
+            Some(next).filter(|&next| occurs(term, next))
+        })
+    }
+
+    // The first token of the phrase whose last token is given, if the
+    // rest of the phrase precedes it
+    fn rest_before(&self, last: Extent) -> Option<Extent> {
+        let init = &self.terms[..self.terms.len() - 1];
+        init.iter().rev().try_fold(last, |next, term| {
+            let previous = self.tokens.rho_prime(next.0.decrement());
+            Some(previous).filter(|&previous| occurs(term, previous))
+        })
+    }
+}
+
+// Whether the extent is in the term's list
+fn occurs<A: Algebra>(term: &A, extent: Extent) -> bool {
+    matches!(extent.0, Position::Valid(_)) && term.tau(extent.0) == extent
+}
+
+impl<A, T> Algebra for Phrase<A, T>
+where
+    A: Algebra,
+    T: Algebra,
+{
+    fn tau(&self, k: Position) -> Extent {
+        let first_term = match self.terms.first() {
+            Some(term) => term,
+            None => return END_EXTENT,
+        };
+
+        let mut k = k;
+        loop {
+            let first = first_term.tau(k);
+            if first == END_EXTENT {
+                return END_EXTENT;
+            }
+            if let Some(last) = self.rest_after(first) {
+                return Extent(first.0, last.1);
+            }
+            k = first.0.increment();
+        }
+    }
+
+    fn tau_prime(&self, k: Position) -> Extent {
+        let last_term = match self.terms.last() {
+            Some(term) => term,
+            None => return START_EXTENT,
+        };
+
+        let mut k = k;
+        loop {
+            let last = last_term.tau_prime(k);
+            if last == START_EXTENT {
+                return START_EXTENT;
+            }
+            if let Some(first) = self.rest_before(last) {
+                return Extent(first.0, last.1);
+            }
+            k = last.1.decrement();
+        }
+    }
+
+    fn rho(&self, k: Position) -> Extent {
+        let last_term = match self.terms.last() {
+            Some(term) => term,
+            None => return END_EXTENT,
+        };
+
+        let mut k = k;
+        loop {
+            let last = last_term.rho(k);
+            if last == END_EXTENT {
+                return END_EXTENT;
+            }
+            if let Some(first) = self.rest_before(last) {
+                return Extent(first.0, last.1);
+            }
+            k = last.1.increment();
+        }
+    }
+
+    fn rho_prime(&self, k: Position) -> Extent {
+        let first_term = match self.terms.first() {
+            Some(term) => term,
+            None => return START_EXTENT,
+        };
+
+        let mut k = k;
+        loop {
+            let first = first_term.rho_prime(k);
+            if first == START_EXTENT {
+                return START_EXTENT;
+            }
+            if let Some(last) = self.rest_after(first) {
+                return Extent(first.0, last.1);
+            }
+            k = first.0.decrement();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AlphabeticTokenizer, Tokenizer, ValidExtent, TOKEN_LAYER};
+    use quickcheck::quickcheck;
+
+    const WORDS: [&str; 3] = ["a", "b", "c"];
+
+    // The extents of every occurrence of the words in order, found by
+    // looking at the tokens directly
+    fn brute_force(tokens: &[(String, ValidExtent)], words: &[&str]) -> Vec<ValidExtent> {
+        tokens
+            .windows(words.len())
+            .filter(|w| w.iter().zip(words).all(|((t, _), word)| t == word))
+            .map(|w| ((w[0].1).0, (w[w.len() - 1].1).1))
+            .collect()
+    }
+
+    #[test]
+    fn phrases_match_consecutive_tokens() {
+        fn prop(text: Vec<u8>, phrase: Vec<u8>) -> bool {
+            let text: Vec<_> = text.iter().map(|&i| WORDS[i as usize % 3]).collect();
+            let text = text.join(" ");
+            let phrase: Vec<_> = phrase
+                .iter()
+                .take(3)
+                .map(|&i| WORDS[i as usize % 3])
+                .collect();
+
+            let index = AlphabeticTokenizer.index(&text);
+            let tokens: Vec<_> = AlphabeticTokenizer.tokenize(&text).collect();
+            let terms = phrase.iter().map(|w| index.term(w)).collect();
+            let found = Phrase::new(terms, index.layer(TOKEN_LAYER));
+            let expected = if phrase.is_empty() {
+                vec![]
+            } else {
+                brute_force(&tokens, &phrase)
+            };
+
+            (&found).iter_tau().eq(expected.iter().copied())
+                && (&found).iter_rho().eq(expected.iter().copied())
+                && (&found).iter_tau_prime().eq(expected.iter().rev().copied())
+                && (&found).iter_rho_prime().eq(expected.iter().rev().copied())
+        }
+
+        quickcheck(prop as fn(_, _) -> _);
+    }
+
+    #[test]
+    fn touching_tokens_are_consecutive() {
+        let tokens = [(0, 3), (3, 6), (6, 9)];
+        let a = [(0, 3)];
+        let b = [(3, 6)];
+
+        let found = Phrase::new(vec![&a[..], &b[..]], &tokens[..]);
+
+        assert_eq!(found.tau(Position::NegativeInfinity), (0, 6));
+        assert_eq!(found.tau(Position::Valid(1)), END_EXTENT);
+    }
+
+    #[test]
+    fn tokens_in_between_prevent_a_match() {
+        let index = AlphabeticTokenizer.index("a c b");
+
+        let found = Phrase::new(
+            vec![index.term("a"), index.term("b")],
+            index.layer(TOKEN_LAYER),
+        );
+
+        assert_eq!(found.iter_tau().count(), 0);
+    }
+}
@@ -0,0 +1,771 @@
+use crate::{Algebra, Index, Tokenizer, TOKEN_LAYER};
+#[cfg(feature = "serde")]
+use serde_json::Value;
+#[cfg(feature = "serde")]
+use std::{error::Error, fmt};
+
+/// Resolves the leaves of a [`Query`] to extent lists.
+pub trait Source {
+    /// The extents of the term, empty if it does not occur.
+    fn lookup_term(&self, term: &str) -> Box<dyn Algebra + Send + Sync + '_>;
+
+    /// The extents of the layer, empty if it does not exist.
+    fn lookup_layer(&self, name: &str) -> Box<dyn Algebra + Send + Sync + '_>;
+
+    /// Whether the layer exists. A source that cannot tell claims
+    /// that every layer does.
+    fn has_layer(&self, _name: &str) -> bool {
+        true
+    }
+}
+
+impl Source for Index {
+    fn lookup_term(&self, term: &str) -> Box<dyn Algebra + Send + Sync + '_> {
+        Box::new(self.term(term))
+    }
+
+    fn lookup_layer(&self, name: &str) -> Box<dyn Algebra + Send + Sync + '_> {
+        Box::new(self.layer(name))
+    }
+
+    fn has_layer(&self, name: &str) -> bool {
+        Index::has_layer(self, name)
+    }
+}
+
+/// A reason that the JSON form of a query could not be read.
+///
+/// Each variant holds the location of the offending value as a [JSON
+/// Pointer][], such as `/2/1` for the first operand of the second
+/// operand of the query. The whole query is the empty pointer.
+///
+/// [JSON Pointer]: https://www.rfc-editor.org/rfc/rfc6901
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// The value is not of the expected kind, such as a number where
+    /// a query or a layer name belongs.
+    UnexpectedValue {
+        path: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The operator is not one of the known operators.
+    UnknownOperator { path: String, operator: String },
+    /// The operator was given the wrong number of operands.
+    WrongArity {
+        path: String,
+        operator: String,
+        expected: usize,
+        found: usize,
+    },
+    /// The layer does not exist in the source.
+    UnknownLayer { path: String, name: String },
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn at(path: &str) -> &str {
+            if path.is_empty() {
+                "the top level"
+            } else {
+                path
+            }
+        }
+
+        match self {
+            QueryError::UnexpectedValue {
+                path,
+                expected,
+                found,
+            } => write!(f, "expected {} at {}, found {}", expected, at(path), found),
+            QueryError::UnknownOperator { path, operator } => {
+                write!(f, "unknown operator {:?} at {}", operator, at(path))
+            }
+            QueryError::WrongArity {
+                path,
+                operator,
+                expected,
+                found,
+            } => write!(
+                f,
+                "operator {:?} at {} takes {} operand(s), found {}",
+                operator,
+                at(path),
+                expected,
+                found
+            ),
+            QueryError::UnknownLayer { path, name } => {
+                write!(f, "unknown layer {:?} at {}", name, at(path))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Error for QueryError {}
+
+// Builds a binary query from its operands
+#[cfg(feature = "serde")]
+pub(crate) type BinaryQuery = fn(Box<Query>, Box<Query>) -> Query;
+
+/// The structure of a query, independent of any index.
+///
+/// Two queries with the same structure find the same extents when
+/// evaluated against the same index, which allows a query to be used
+/// as a cache key.
+///
+/// Reading and writing the JSON form of a query, with
+/// `Query::from_json` and `Query::to_json`, requires the `serde`
+/// feature. That feature also serializes a query in the same form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Query {
+    /// Finds nothing
+    Empty,
+    /// The occurrences of a term
+    Term(String),
+    /// The extents of a named layer
+    Layer(String),
+    /// Consecutive occurrences of the terms, as found by
+    /// [`Phrase`](crate::Phrase)
+    Phrase(Vec<String>),
+    ContainedIn(Box<Query>, Box<Query>),
+    Containing(Box<Query>, Box<Query>),
+    NotContainedIn(Box<Query>, Box<Query>),
+    NotContaining(Box<Query>, Box<Query>),
+    BothOf(Box<Query>, Box<Query>),
+    OneOf(Box<Query>, Box<Query>),
+    FollowedBy(Box<Query>, Box<Query>),
+}
+
+#[cfg(feature = "serde")]
+impl Query {
+    /// Reads a query from its JSON form.
+    ///
+    /// A string is a [`Query::Term`]. Any other query is an array
+    /// whose first element names the operator and whose remaining
+    /// elements are its operands:
+    ///
+    /// | JSON              | Query                     |
+    /// |-------------------|---------------------------|
+    /// | `["E"]`           | [`Query::Empty`]          |
+    /// | `["L", "name"]`   | [`Query::Layer`]          |
+    /// | `["P", "a", ...]` | [`Query::Phrase`]         |
+    /// | `["<", a, b]`     | [`Query::ContainedIn`]    |
+    /// | `[">", a, b]`     | [`Query::Containing`]     |
+    /// | `["/<", a, b]`    | [`Query::NotContainedIn`] |
+    /// | `["/>", a, b]`    | [`Query::NotContaining`]  |
+    /// | `["&", a, b]`     | [`Query::BothOf`]         |
+    /// | `["\|", a, b]`    | [`Query::OneOf`]          |
+    /// | `["->", a, b]`    | [`Query::FollowedBy`]     |
+    ///
+    /// A phrase has one or more terms. Any other value is an error, as
+    /// is an operator with the wrong number of operands.
+    ///
+    /// Requires the `serde` feature.
+    pub fn from_json(json: &Value) -> Result<Query, QueryError> {
+        Self::from_json_value(json, &mut String::new(), &|_| true)
+    }
+
+    /// Reads a query from its JSON form, as [`Query::from_json`], and
+    /// checks that each of its layers exists in the source.
+    pub fn from_json_in<S>(json: &Value, source: &S) -> Result<Query, QueryError>
+    where
+        S: Source + ?Sized,
+    {
+        Self::from_json_value(json, &mut String::new(), &|name| source.has_layer(name))
+    }
+
+    /// The JSON form of the query, as read by [`Query::from_json`].
+    pub fn to_json(&self) -> Value {
+        use self::Query::*;
+
+        let operator = match self {
+            Empty => return Value::from(vec!["E"]),
+            Term(t) => return Value::from(t.as_str()),
+            Layer(l) => return Value::from(vec!["L", l]),
+            Phrase(terms) => {
+                let elements = Some("P")
+                    .into_iter()
+                    .chain(terms.iter().map(String::as_str));
+                return Value::from(elements.collect::<Vec<_>>());
+            }
+            _ => self.binary_operator().expect("Only binary queries remain"),
+        };
+        let (a, b) = self.children().expect("Operators have two operands");
+        Value::Array(vec![operator.into(), a.to_json(), b.to_json()])
+    }
+
+    // The path is extended while reading an element and restored
+    // afterwards, so that it always locates the current value.
+    fn from_json_value(
+        json: &Value,
+        path: &mut String,
+        has_layer: &dyn Fn(&str) -> bool,
+    ) -> Result<Query, QueryError> {
+        use self::Query::*;
+
+        let elements = match json {
+            Value::String(term) => return Ok(Term(term.clone())),
+            Value::Array(elements) => elements,
+            other => return Err(unexpected(path, "a term or an operator array", other)),
+        };
+
+        let operator = match elements.first() {
+            Some(Value::String(operator)) => operator,
+            Some(other) => {
+                let path = format!("{}/0", path);
+                return Err(unexpected(&path, "an operator", other));
+            }
+            None => return Err(unexpected(path, "an operator array", json)),
+        };
+
+        if operator == "P" {
+            return Self::phrase_from_json(&elements[1..], path);
+        }
+
+        let binary = Self::binary(operator);
+        let expected = match operator.as_str() {
+            "E" => 0,
+            "L" => 1,
+            _ if binary.is_some() => 2,
+            _ => {
+                return Err(QueryError::UnknownOperator {
+                    path: format!("{}/0", path),
+                    operator: operator.clone(),
+                })
+            }
+        };
+        if elements.len() - 1 != expected {
+            return Err(QueryError::WrongArity {
+                path: path.clone(),
+                operator: operator.clone(),
+                expected,
+                found: elements.len() - 1,
+            });
+        }
+
+        let len = path.len();
+        let mut operand = |idx: usize| {
+            path.push_str(&format!("/{}", idx));
+            let query = Self::from_json_value(&elements[idx], path, has_layer);
+            path.truncate(len);
+            query.map(Box::new)
+        };
+
+        if let Some(binary) = binary {
+            return Ok(binary(operand(1)?, operand(2)?));
+        }
+
+        let query = match operator.as_str() {
+            "E" => Empty,
+            "L" => {
+                let layer_path = format!("{}/1", path);
+                let name = match &elements[1] {
+                    Value::String(name) => name,
+                    other => return Err(unexpected(&layer_path, "a layer name", other)),
+                };
+                if !has_layer(name) {
+                    return Err(QueryError::UnknownLayer {
+                        path: layer_path,
+                        name: name.clone(),
+                    });
+                }
+                Layer(name.clone())
+            }
+            _ => unreachable!("Operators are checked above"),
+        };
+        Ok(query)
+    }
+
+    fn phrase_from_json(terms: &[Value], path: &str) -> Result<Query, QueryError> {
+        if terms.is_empty() {
+            return Err(QueryError::WrongArity {
+                path: path.to_string(),
+                operator: "P".into(),
+                expected: 1,
+                found: 0,
+            });
+        }
+
+        let terms = terms.iter().enumerate().map(|(idx, term)| match term {
+            Value::String(term) => Ok(term.clone()),
+            other => {
+                let path = format!("{}/{}", path, idx + 1);
+                Err(unexpected(&path, "a term", other))
+            }
+        });
+        terms.collect::<Result<_, _>>().map(Query::Phrase)
+    }
+
+    // The operator naming a binary query in the JSON form
+    pub(crate) fn binary_operator(&self) -> Option<&'static str> {
+        use self::Query::*;
+
+        Some(match self {
+            Empty | Term(_) | Layer(_) | Phrase(_) => return None,
+            ContainedIn(..) => "<",
+            Containing(..) => ">",
+            NotContainedIn(..) => "/<",
+            NotContaining(..) => "/>",
+            BothOf(..) => "&",
+            OneOf(..) => "|",
+            FollowedBy(..) => "->",
+        })
+    }
+
+    // The binary query named by an operator of the JSON form
+    pub(crate) fn binary(operator: &str) -> Option<BinaryQuery> {
+        use self::Query::*;
+
+        Some(match operator {
+            "<" => ContainedIn,
+            ">" => Containing,
+            "/<" => NotContainedIn,
+            "/>" => NotContaining,
+            "&" => BothOf,
+            "|" => OneOf,
+            "->" => FollowedBy,
+            _ => return None,
+        })
+    }
+}
+
+impl Query {
+    /// The operands of a binary operator.
+    pub fn children(&self) -> Option<(&Query, &Query)> {
+        use self::Query::*;
+
+        match self {
+            Empty | Term(_) | Layer(_) | Phrase(_) => None,
+            ContainedIn(a, b)
+            | Containing(a, b)
+            | NotContainedIn(a, b)
+            | NotContaining(a, b)
+            | BothOf(a, b)
+            | OneOf(a, b)
+            | FollowedBy(a, b) => Some((a, b)),
+        }
+    }
+
+    /// Rewrites each term as the tokenizer would have indexed it, so
+    /// that the term `Running` can find an indexed `run`.
+    ///
+    /// A term that the tokenizer drops entirely, such as a stop word,
+    /// becomes [`Query::Empty`]. A term that splits into several
+    /// tokens, such as `Hello, World` or a run of CJK ideographs,
+    /// becomes a [`Query::Phrase`] of those tokens, so that they are
+    /// found next to each other. Each term of a phrase is rewritten
+    /// in the same way.
+    pub fn analyze<T>(&self, tokenizer: &T) -> Query
+    where
+        T: Tokenizer + ?Sized,
+    {
+        use self::Query::*;
+
+        let both = |a: &Query, b: &Query| {
+            (
+                Box::new(a.analyze(tokenizer)),
+                Box::new(b.analyze(tokenizer)),
+            )
+        };
+
+        match self {
+            Empty => Empty,
+            Term(t) => phrase(tokenizer.tokenize(t).map(|(term, _)| term).collect()),
+            Layer(l) => Layer(l.clone()),
+            Phrase(terms) => phrase(
+                terms
+                    .iter()
+                    .flat_map(|t| tokenizer.tokenize(t))
+                    .map(|(term, _)| term)
+                    .collect(),
+            ),
+            ContainedIn(a, b) => {
+                let (a, b) = both(a, b);
+                ContainedIn(a, b)
+            }
+            Containing(a, b) => {
+                let (a, b) = both(a, b);
+                Containing(a, b)
+            }
+            NotContainedIn(a, b) => {
+                let (a, b) = both(a, b);
+                NotContainedIn(a, b)
+            }
+            NotContaining(a, b) => {
+                let (a, b) = both(a, b);
+                NotContaining(a, b)
+            }
+            BothOf(a, b) => {
+                let (a, b) = both(a, b);
+                BothOf(a, b)
+            }
+            OneOf(a, b) => {
+                let (a, b) = both(a, b);
+                OneOf(a, b)
+            }
+            FollowedBy(a, b) => {
+                let (a, b) = both(a, b);
+                FollowedBy(a, b)
+            }
+        }
+    }
+
+    /// Builds the lazily-evaluated operator tree for this query,
+    /// borrowing the posting lists from the source.
+    pub fn evaluate<'a, S>(&self, source: &'a S) -> Box<dyn Algebra + Send + Sync + 'a>
+    where
+        S: Source + ?Sized,
+    {
+        self.evaluate_with(source, &mut |q, source| q.evaluate(source))
+    }
+
+    /// Builds the lazily-evaluated operator tree for this query,
+    /// sharing ownership of the posting lists with the index.
+    ///
+    /// The tree does not borrow the index, so it may be moved to
+    /// another thread or outlive the index.
+    pub fn evaluate_shared(&self, index: &Index) -> Box<dyn Algebra + Send + Sync + 'static> {
+        match self {
+            Query::Term(t) => Box::new(index.shared_term(t)),
+            Query::Layer(l) => Box::new(index.shared_layer(l)),
+            Query::Phrase(terms) => Box::new(crate::Phrase::new(
+                terms.iter().map(|t| index.shared_term(t)).collect(),
+                index.shared_layer(TOKEN_LAYER),
+            )),
+            _ => self.combine(index, &mut |q, index| q.evaluate_shared(index)),
+        }
+    }
+
+    /// Builds the operator for the root of this query, using `child`
+    /// to build the operands.
+    pub(crate) fn evaluate_with<'a, S, F>(
+        &self,
+        source: &'a S,
+        child: &mut F,
+    ) -> Box<dyn Algebra + Send + Sync + 'a>
+    where
+        S: Source + ?Sized,
+        F: FnMut(&Query, &'a S) -> Box<dyn Algebra + Send + Sync + 'a>,
+    {
+        match self {
+            Query::Term(t) => source.lookup_term(t),
+            Query::Layer(l) => source.lookup_layer(l),
+            Query::Phrase(terms) => Box::new(crate::Phrase::new(
+                terms.iter().map(|t| source.lookup_term(t)).collect(),
+                source.lookup_layer(TOKEN_LAYER),
+            )),
+            _ => self.combine(source, child),
+        }
+    }
+
+    fn combine<'s, 'a, S, F>(
+        &self,
+        source: &'s S,
+        child: &mut F,
+    ) -> Box<dyn Algebra + Send + Sync + 'a>
+    where
+        S: ?Sized,
+        F: FnMut(&Query, &'s S) -> Box<dyn Algebra + Send + Sync + 'a>,
+    {
+        use self::Query::*;
+
+        let mut both = |a, b| (child(a, source), child(b, source));
+
+        match self {
+            Empty | Term(_) | Layer(_) | Phrase(_) => Box::new(crate::Empty),
+            ContainedIn(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::ContainedIn::new(a, b))
+            }
+            Containing(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::Containing::new(a, b))
+            }
+            NotContainedIn(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::NotContainedIn::new(a, b))
+            }
+            NotContaining(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::NotContaining::new(a, b))
+            }
+            BothOf(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::BothOf::new(a, b))
+            }
+            OneOf(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::OneOf::new(a, b))
+            }
+            FollowedBy(a, b) => {
+                let (a, b) = both(a, b);
+                Box::new(crate::FollowedBy::new(a, b))
+            }
+        }
+    }
+}
+
+// A single term stays a term, and no terms find nothing
+fn phrase(mut terms: Vec<String>) -> Query {
+    match terms.len() {
+        0 => Query::Empty,
+        1 => Query::Term(terms.remove(0)),
+        _ => Query::Phrase(terms),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn unexpected(path: &str, expected: &'static str, found: &Value) -> QueryError {
+    let found = match found {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(a) if a.is_empty() => "an empty array",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    };
+    QueryError::UnexpectedValue {
+        path: path.to_string(),
+        expected,
+        found,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AlphabeticTokenizer, EnglishStemmer, Pipeline, StopWords, UnicodeWordTokenizer};
+    use std::{sync::Arc, thread};
+
+    fn sample_index() -> Index {
+        let mut index = Index::new();
+        index.extend_term("hello", vec![(0, 5)]);
+        index.extend_term("world", vec![(7, 12), (23, 28)]);
+        index.extend_layer("sentence", vec![(0, 13), (14, 29)]);
+        index
+    }
+
+    fn world_in_hello_sentence() -> Query {
+        Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(Query::Containing(
+                Box::new(Query::Layer("sentence".into())),
+                Box::new(Query::Term("hello".into())),
+            )),
+        )
+    }
+
+    #[test]
+    fn borrowed_and_shared_evaluation_agree() {
+        let index = sample_index();
+        let query = world_in_hello_sentence();
+
+        let borrowed: Vec<_> = query.evaluate(&index).iter_tau().collect();
+        let shared: Vec<_> = query.evaluate_shared(&index).iter_tau().collect();
+
+        assert_eq!(borrowed, [(7, 12)]);
+        assert_eq!(borrowed, shared);
+    }
+
+    #[test]
+    fn queries_are_evaluated_concurrently_on_one_index() {
+        let index = Arc::new(sample_index());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let index = Arc::clone(&index);
+                thread::spawn(move || {
+                    let op = world_in_hello_sentence().evaluate_shared(&index);
+                    op.iter_tau().collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), [(7, 12)]);
+        }
+    }
+
+    #[test]
+    fn shared_tree_is_unaffected_by_later_index_changes() {
+        let mut index = sample_index();
+        let op = Query::Term("world".into()).evaluate_shared(&index);
+
+        index.extend_term("world", vec![(40, 45)]);
+
+        assert_eq!(op.iter_tau().count(), 2);
+        assert_eq!(index.term("world").len(), 3);
+    }
+
+    #[test]
+    fn analyzed_query_finds_stemmed_terms() {
+        let pipeline = Pipeline::new(AlphabeticTokenizer)
+            .with_filter(StopWords::english())
+            .with_filter(EnglishStemmer::new());
+        let index = pipeline.index("She runs. He is running.");
+
+        let query = Query::Term("Running".into()).analyze(&pipeline);
+
+        assert_eq!(query, Query::Term("run".into()));
+        assert_eq!(
+            query.evaluate(&index).iter_tau().collect::<Vec<_>>(),
+            [(4, 8), (16, 23)]
+        );
+    }
+
+    fn found_text<'t>(query: &Query, index: &Index, text: &'t str) -> Vec<&'t str> {
+        query
+            .evaluate(index)
+            .iter_tau()
+            .map(|(s, e)| &text[s as usize..e as usize])
+            .collect()
+    }
+
+    #[test]
+    fn analyzed_cjk_term_finds_adjacent_ideographs() {
+        let text = "我住在東京。東は京の東。";
+        let index = UnicodeWordTokenizer.index(text);
+
+        let query = Query::Term("東京".into()).analyze(&UnicodeWordTokenizer);
+
+        assert_eq!(query, Query::Phrase(vec!["東".into(), "京".into()]));
+        assert_eq!(found_text(&query, &index, text), ["東京"]);
+        assert_eq!(query.evaluate_shared(&index).iter_tau().count(), 1);
+    }
+
+    #[test]
+    fn analyzed_words_are_found_as_a_phrase() {
+        let text = "Hello there, world. Hello, World!";
+        let index = AlphabeticTokenizer.index(text);
+
+        let query = Query::Term("Hello, World".into()).analyze(&AlphabeticTokenizer);
+
+        assert_eq!(found_text(&query, &index, text), ["Hello, World"]);
+        assert!(query.evaluate(&index).iter_tau_prime().eq(vec![(20, 32)]));
+    }
+
+    #[test]
+    fn analyzed_query_rewrites_every_term() {
+        let pipeline = Pipeline::new(AlphabeticTokenizer).with_filter(StopWords::english());
+        let term = |t: &str| Box::new(Query::Term(t.into()));
+
+        let query = Query::Containing(
+            Box::new(Query::Layer("Sentence".into())),
+            Box::new(Query::OneOf(term("The"), term("Hello, World"))),
+        );
+
+        assert_eq!(
+            query.analyze(&pipeline),
+            Query::Containing(
+                Box::new(Query::Layer("Sentence".into())),
+                Box::new(Query::OneOf(
+                    Box::new(Query::Empty),
+                    Box::new(Query::Phrase(vec!["hello".into(), "world".into()])),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn json_queries_round_trip() {
+        let json = serde_json::json!(["<", "world", [">", ["L", "sentence"], "hello"]]);
+
+        let query = Query::from_json(&json).unwrap();
+
+        assert_eq!(query, world_in_hello_sentence());
+        assert_eq!(query.to_json(), json);
+        assert_eq!(Query::from_json(&Query::Empty.to_json()), Ok(Query::Empty));
+
+        let phrase = Query::Phrase(vec!["hello".into(), "world".into()]);
+        assert_eq!(phrase.to_json(), serde_json::json!(["P", "hello", "world"]));
+        assert_eq!(Query::from_json(&phrase.to_json()), Ok(phrase));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn unexpected_json_values_are_errors() {
+        let error = |json| Query::from_json(&json).unwrap_err();
+
+        assert_eq!(
+            error(serde_json::json!(["&", "a", 7])),
+            QueryError::UnexpectedValue {
+                path: "/2".into(),
+                expected: "a term or an operator array",
+                found: "a number",
+            }
+        );
+        assert_eq!(
+            error(serde_json::json!([">", {}, "a"])),
+            QueryError::UnexpectedValue {
+                path: "/1".into(),
+                expected: "a term or an operator array",
+                found: "an object",
+            }
+        );
+        assert_eq!(
+            error(serde_json::json!(["|", "a", []])),
+            QueryError::UnexpectedValue {
+                path: "/2".into(),
+                expected: "an operator array",
+                found: "an empty array",
+            }
+        );
+        assert_eq!(
+            error(serde_json::json!(["L", null])),
+            QueryError::UnexpectedValue {
+                path: "/1".into(),
+                expected: "a layer name",
+                found: "null",
+            }
+        );
+        assert_eq!(
+            error(serde_json::json!(["->", "a", ["<<", "b", "c"]])),
+            QueryError::UnknownOperator {
+                path: "/2/0".into(),
+                operator: "<<".into(),
+            }
+        );
+        assert_eq!(
+            error(serde_json::json!(["P", "a", ["L", "s"]])),
+            QueryError::UnexpectedValue {
+                path: "/2".into(),
+                expected: "a term",
+                found: "an array",
+            }
+        );
+        assert_eq!(
+            error(serde_json::json!(["<", "a"])),
+            QueryError::WrongArity {
+                path: "".into(),
+                operator: "<".into(),
+                expected: 2,
+                found: 1,
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn json_layers_must_exist_in_the_source() {
+        let index = sample_index();
+        let json = serde_json::json!(["&", ["L", "sentence"], [">", ["L", "paragraph"], "a"]]);
+
+        assert!(Query::from_json(&json).is_ok());
+        assert_eq!(
+            Query::from_json_in(&json, &index),
+            Err(QueryError::UnknownLayer {
+                path: "/2/1/1".into(),
+                name: "paragraph".into(),
+            })
+        );
+        assert_eq!(
+            Query::from_json_in(&json, &index).unwrap_err().to_string(),
+            r#"unknown layer "paragraph" at /2/1/1"#
+        );
+    }
+}
@@ -0,0 +1,520 @@
+use crate::{
+    doc_and_offset_to_k, Algebra, Deletions, Documents, Index, MergedLists, SkipDeleted, Source,
+    ValidExtent, DOC_OFFSET_MAX,
+};
+use std::{error::Error, fmt, ops::Range, sync::Arc};
+
+/// A reason that a document could not be written to an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+    /// Every document number has been allocated.
+    TooManyDocuments,
+    /// An extent does not fit within a single document.
+    ExtentOutsideDocument(ValidExtent),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::TooManyDocuments => "the index cannot hold any more documents".fmt(f),
+            WriteError::ExtentOutsideDocument(e) => {
+                write!(f, "extent {:?} does not fit within a document", e)
+            }
+        }
+    }
+}
+
+impl Error for WriteError {}
+
+/// A reason that a merge could not be applied to an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// The merged segments are no longer part of the index, such as
+    /// when another merge replaced them first.
+    SegmentsChanged,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::SegmentsChanged => "the merged segments are no longer in the index".fmt(f),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+/// An immutable portion of a [`SegmentedIndex`] holding the posting
+/// lists of a contiguous range of documents.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    documents: Range<u32>,
+    index: Index,
+}
+
+impl Segment {
+    pub fn documents(&self) -> Range<u32> {
+        self.documents.clone()
+    }
+
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+}
+
+/// An index that grows by appending documents.
+///
+/// Documents are added through an [`IndexWriter`], each commit of
+/// which produces a new immutable segment. Queries see the posting
+/// lists of all segments as if they were a single list. Segments may
+/// be compacted into one by merging, which can be performed without
+/// holding on to the index.
+///
+/// Deleted documents remain in their segments but are hidden from
+/// every query until a merge removes them.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentedIndex {
+    // Ordered by document range, with no gaps between them
+    segments: Vec<Arc<Segment>>,
+    document_count: u32,
+    deletions: Deletions,
+}
+
+impl SegmentedIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The extents of every document in the index that has not been
+    /// deleted.
+    pub fn documents(&self) -> SkipDeleted<'_, Documents> {
+        SkipDeleted::new(Documents::new(self.document_count), &self.deletions)
+    }
+
+    /// Hides the document from all queries, returning `false` if it
+    /// does not exist or was already deleted.
+    pub fn delete_document(&mut self, doc: u32) -> bool {
+        doc < self.document_count && self.deletions.insert(doc)
+    }
+
+    pub fn is_deleted(&self, doc: u32) -> bool {
+        self.deletions.contains(doc)
+    }
+
+    pub fn deletions(&self) -> &Deletions {
+        &self.deletions
+    }
+
+    pub fn segments(&self) -> &[Arc<Segment>] {
+        &self.segments
+    }
+
+    /// Starts adding documents after those already in the index.
+    pub fn writer(&mut self) -> IndexWriter<'_> {
+        let next_document = self.document_count;
+        IndexWriter {
+            target: self,
+            pending: Index::new(),
+            first_document: next_document,
+            next_document,
+        }
+    }
+
+    /// Prepares to merge the given consecutive segments into one.
+    ///
+    /// The returned merge does not borrow the index, so the work may
+    /// be performed elsewhere while the index continues to be used.
+    /// Documents deleted before this point are removed from the
+    /// merged segment.
+    pub fn begin_merge(&self, segments: Range<usize>) -> Merge {
+        Merge {
+            segments: self.segments[segments].to_vec(),
+            deletions: self.deletions.clone(),
+        }
+    }
+
+    /// Replaces the segments of a completed merge with the merged
+    /// segment.
+    pub fn finish_merge(&mut self, merge: CompletedMerge) -> Result<(), MergeError> {
+        let first = match merge.replaced.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        let start = self
+            .segments
+            .iter()
+            .position(|s| Arc::ptr_eq(s, first))
+            .ok_or(MergeError::SegmentsChanged)?;
+        let end = start + merge.replaced.len();
+
+        let current = self
+            .segments
+            .get(start..end)
+            .ok_or(MergeError::SegmentsChanged)?;
+        let unchanged = current
+            .iter()
+            .zip(&merge.replaced)
+            .all(|(a, b)| Arc::ptr_eq(a, b));
+        if !unchanged {
+            return Err(MergeError::SegmentsChanged);
+        }
+
+        self.segments
+            .splice(start..end, Some(Arc::new(merge.merged)));
+        Ok(())
+    }
+
+    /// Merges every segment into one.
+    pub fn merge_all(&mut self) {
+        let merge = self.begin_merge(0..self.segments.len()).run();
+        self.finish_merge(merge)
+            .expect("Segments cannot change while merging");
+    }
+
+    fn lookup<'a, F>(&'a self, list: F) -> Box<dyn Algebra + Send + Sync + 'a>
+    where
+        F: Fn(&'a Index) -> &'a [ValidExtent],
+    {
+        let all = MergedLists::new(self.segments.iter().map(|s| list(&s.index)));
+
+        if self.deletions.is_empty() {
+            Box::new(all)
+        } else {
+            Box::new(SkipDeleted::new(all, &self.deletions))
+        }
+    }
+}
+
+impl Source for SegmentedIndex {
+    fn lookup_term(&self, term: &str) -> Box<dyn Algebra + Send + Sync + '_> {
+        self.lookup(|index| index.term(term))
+    }
+
+    fn lookup_layer(&self, name: &str) -> Box<dyn Algebra + Send + Sync + '_> {
+        self.lookup(|index| index.layer(name))
+    }
+
+    fn has_layer(&self, name: &str) -> bool {
+        self.segments.iter().any(|s| s.index.has_layer(name))
+    }
+}
+
+/// Appends documents to a [`SegmentedIndex`].
+///
+/// The documents become visible as a new segment when the writer is
+/// committed. Dropping the writer discards them.
+#[derive(Debug)]
+pub struct IndexWriter<'a> {
+    target: &'a mut SegmentedIndex,
+    pending: Index,
+    first_document: u32,
+    next_document: u32,
+}
+
+impl<'a> IndexWriter<'a> {
+    /// Adds a document, returning its number.
+    ///
+    /// The extents of the document's index are relative to the start
+    /// of the document.
+    pub fn add_document(&mut self, document: &Index) -> Result<u32, WriteError> {
+        let doc = self.next_document;
+        if doc == u32::MAX {
+            return Err(WriteError::TooManyDocuments);
+        }
+
+        let lists = document.terms().chain(document.layers());
+        for (_, extents) in lists {
+            if let Some(&e) = extents.iter().find(|e| e.1 > u64::from(DOC_OFFSET_MAX)) {
+                return Err(WriteError::ExtentOutsideDocument(e));
+            }
+        }
+
+        let base = doc_and_offset_to_k(doc, 0);
+        let shift = |extents: &[ValidExtent]| -> Vec<ValidExtent> {
+            extents.iter().map(|&(s, e)| (base + s, base + e)).collect()
+        };
+
+        for (term, extents) in document.terms() {
+            self.pending.extend_term(term, shift(extents));
+        }
+        for (name, extents) in document.layers() {
+            self.pending.extend_layer(name, shift(extents));
+        }
+
+        self.next_document += 1;
+        Ok(doc)
+    }
+
+    /// Makes the added documents visible as a new segment.
+    pub fn commit(self) {
+        if self.next_document == self.first_document {
+            return;
+        }
+
+        let segment = Segment {
+            documents: self.first_document..self.next_document,
+            index: self.pending,
+        };
+
+        self.target.segments.push(Arc::new(segment));
+        self.target.document_count = self.next_document;
+    }
+}
+
+/// Segments waiting to be merged, created by
+/// [`SegmentedIndex::begin_merge`].
+#[derive(Debug, Clone)]
+pub struct Merge {
+    segments: Vec<Arc<Segment>>,
+    deletions: Deletions,
+}
+
+impl Merge {
+    /// Combines the posting lists of the segments, dropping the
+    /// extents of deleted documents.
+    pub fn run(self) -> CompletedMerge {
+        let documents = match (self.segments.first(), self.segments.last()) {
+            (Some(first), Some(last)) => first.documents.start..last.documents.end,
+            _ => 0..0,
+        };
+
+        let deletions = &self.deletions;
+        let live = |extents: &[ValidExtent]| -> Vec<ValidExtent> {
+            extents
+                .iter()
+                .copied()
+                .filter(|&e| !deletions.covers(e))
+                .collect()
+        };
+
+        let mut index = Index::new();
+        for segment in &self.segments {
+            for (term, extents) in segment.index.terms() {
+                let extents = live(extents);
+                if !extents.is_empty() {
+                    index.extend_term(term, extents);
+                }
+            }
+            for (name, extents) in segment.index.layers() {
+                let extents = live(extents);
+                if !extents.is_empty() {
+                    index.extend_layer(name, extents);
+                }
+            }
+        }
+
+        CompletedMerge {
+            replaced: self.segments,
+            merged: Segment { documents, index },
+        }
+    }
+}
+
+/// The result of a [`Merge`], to be applied with
+/// [`SegmentedIndex::finish_merge`].
+#[derive(Debug, Clone)]
+pub struct CompletedMerge {
+    replaced: Vec<Arc<Segment>>,
+    merged: Segment,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Query;
+    use std::thread;
+
+    // "hello world. goodbye world."
+    fn sample_document() -> Index {
+        let mut index = Index::new();
+        index.extend_term("hello", vec![(0, 5)]);
+        index.extend_term("world", vec![(6, 11), (21, 26)]);
+        index.extend_term("goodbye", vec![(13, 20)]);
+        index.extend_layer("sentence", vec![(0, 12), (13, 27)]);
+        index
+    }
+
+    fn add_documents(index: &mut SegmentedIndex, count: usize) {
+        let mut writer = index.writer();
+        for _ in 0..count {
+            writer.add_document(&sample_document()).unwrap();
+        }
+        writer.commit();
+    }
+
+    fn world_in_goodbye_sentence() -> Query {
+        Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(Query::Containing(
+                Box::new(Query::Layer("sentence".into())),
+                Box::new(Query::Term("goodbye".into())),
+            )),
+        )
+    }
+
+    fn expected_worlds(count: u32) -> Vec<ValidExtent> {
+        (0..count)
+            .map(|doc| {
+                let base = doc_and_offset_to_k(doc, 0);
+                (base + 21, base + 26)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writer_allocates_consecutive_documents() {
+        let mut index = SegmentedIndex::new();
+        add_documents(&mut index, 2);
+
+        let mut writer = index.writer();
+        assert_eq!(writer.add_document(&sample_document()), Ok(2));
+        assert_eq!(writer.add_document(&sample_document()), Ok(3));
+        writer.commit();
+
+        assert_eq!(index.documents().iter_tau().count(), 4);
+        assert_eq!(index.segments().len(), 2);
+        assert_eq!(index.segments()[1].documents(), 2..4);
+    }
+
+    #[test]
+    fn uncommitted_documents_are_discarded() {
+        let mut index = SegmentedIndex::new();
+        add_documents(&mut index, 1);
+
+        let mut writer = index.writer();
+        writer.add_document(&sample_document()).unwrap();
+        drop(writer);
+
+        assert_eq!(index.documents().iter_tau().count(), 1);
+        assert_eq!(index.segments().len(), 1);
+    }
+
+    #[test]
+    fn extents_beyond_a_document_are_rejected() {
+        let mut document = Index::new();
+        document.extend_term("big", vec![(0, 1 << 32)]);
+        let mut index = SegmentedIndex::new();
+
+        let err = index.writer().add_document(&document).unwrap_err();
+
+        assert_eq!(err, WriteError::ExtentOutsideDocument((0, 1 << 32)));
+    }
+
+    #[test]
+    fn segments_are_queried_as_one_list() {
+        let mut index = SegmentedIndex::new();
+        add_documents(&mut index, 2);
+        add_documents(&mut index, 1);
+        add_documents(&mut index, 3);
+
+        let query = world_in_goodbye_sentence();
+        let forwards: Vec<_> = query.evaluate(&index).iter_tau().collect();
+        let mut backwards: Vec<_> = query.evaluate(&index).iter_tau_prime().collect();
+        backwards.reverse();
+
+        assert_eq!(forwards, expected_worlds(6));
+        assert_eq!(backwards, forwards);
+    }
+
+    #[test]
+    fn merging_preserves_results() {
+        let mut index = SegmentedIndex::new();
+        for _ in 0..4 {
+            add_documents(&mut index, 2);
+        }
+
+        index.merge_all();
+
+        assert_eq!(index.segments().len(), 1);
+        assert_eq!(index.segments()[0].documents(), 0..8);
+        let found: Vec<_> = world_in_goodbye_sentence()
+            .evaluate(&index)
+            .iter_tau()
+            .collect();
+        assert_eq!(found, expected_worlds(8));
+    }
+
+    #[test]
+    fn merge_runs_in_the_background_while_documents_are_added() {
+        let mut index = SegmentedIndex::new();
+        add_documents(&mut index, 1);
+        add_documents(&mut index, 1);
+
+        let merge = index.begin_merge(0..2);
+        let worker = thread::spawn(move || merge.run());
+        add_documents(&mut index, 1);
+        index.finish_merge(worker.join().unwrap()).unwrap();
+
+        let documents: Vec<_> = index.segments().iter().map(|s| s.documents()).collect();
+        assert_eq!(documents, [0..2, 2..3]);
+        let found: Vec<_> = world_in_goodbye_sentence()
+            .evaluate(&index)
+            .iter_tau()
+            .collect();
+        assert_eq!(found, expected_worlds(3));
+    }
+
+    #[test]
+    fn stale_merge_is_rejected() {
+        let mut index = SegmentedIndex::new();
+        for _ in 0..3 {
+            add_documents(&mut index, 1);
+        }
+
+        let stale = index.begin_merge(1..3).run();
+        index.merge_all();
+
+        assert_eq!(index.finish_merge(stale), Err(MergeError::SegmentsChanged));
+        assert_eq!(index.segments().len(), 1);
+    }
+
+    #[test]
+    fn deleted_documents_are_hidden_from_queries() {
+        let mut index = SegmentedIndex::new();
+        add_documents(&mut index, 3);
+        add_documents(&mut index, 2);
+
+        assert!(index.delete_document(1));
+        assert!(index.delete_document(3));
+        assert!(!index.delete_document(3));
+        assert!(!index.delete_document(5));
+
+        let query = world_in_goodbye_sentence();
+        let expected: Vec<_> = expected_worlds(5)
+            .into_iter()
+            .enumerate()
+            .filter(|&(doc, _)| doc != 1 && doc != 3)
+            .map(|(_, e)| e)
+            .collect();
+
+        let forwards: Vec<_> = query.evaluate(&index).iter_tau().collect();
+        let mut backwards: Vec<_> = query.evaluate(&index).iter_rho_prime().collect();
+        backwards.reverse();
+
+        assert_eq!(forwards, expected);
+        assert_eq!(backwards, expected);
+        assert_eq!(index.documents().iter_tau().count(), 3);
+    }
+
+    #[test]
+    fn merging_purges_deleted_documents() {
+        let mut index = SegmentedIndex::new();
+        add_documents(&mut index, 2);
+        add_documents(&mut index, 1);
+        index.delete_document(0);
+        index.delete_document(2);
+
+        index.merge_all();
+
+        let merged = index.segments()[0].index();
+        assert_eq!(merged.term("hello").len(), 1);
+        assert_eq!(merged.term("world").len(), 2);
+        assert!(index.is_deleted(0));
+        let found: Vec<_> = world_in_goodbye_sentence()
+            .evaluate(&index)
+            .iter_tau()
+            .collect();
+        assert_eq!(found, &expected_worlds(2)[1..]);
+    }
+}
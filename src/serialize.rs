@@ -0,0 +1,380 @@
+//! Serialization of the types that are not derived.
+//!
+//! In human-readable formats such as JSON, a [`Position`] is its
+//! offset, or one of the strings `"-inf"` and `"+inf"`, so that an
+//! [`Extent`](crate::Extent) is written like `[3, "+inf"]`. Other
+//! formats use an enum of the three variants.
+//!
+//! A [`Query`] is written in the JSON form read by `Query::from_json`,
+//! as a term or a sequence of an operator and its operands. So that
+//! formats that are not self-describing can tell the two apart, a
+//! term is written in them as the sequence `["T", term]`.
+
+use crate::{Position, Query};
+use serde::{
+    de::{self, IgnoredAny, SeqAccess, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{convert::TryFrom, fmt};
+
+const NEGATIVE_INFINITY: &str = "-inf";
+const POSITIVE_INFINITY: &str = "+inf";
+
+// The representation in formats that are not human-readable
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Position")]
+enum Compact {
+    NegativeInfinity,
+    Valid(u64),
+    PositiveInfinity,
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            match *self {
+                Position::NegativeInfinity => serializer.serialize_str(NEGATIVE_INFINITY),
+                Position::Valid(offset) => serializer.serialize_u64(offset),
+                Position::PositiveInfinity => serializer.serialize_str(POSITIVE_INFINITY),
+            }
+        } else {
+            let compact = match *self {
+                Position::NegativeInfinity => Compact::NegativeInfinity,
+                Position::Valid(offset) => Compact::Valid(offset),
+                Position::PositiveInfinity => Compact::PositiveInfinity,
+            };
+            compact.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(HumanReadable)
+        } else {
+            Ok(match Compact::deserialize(deserializer)? {
+                Compact::NegativeInfinity => Position::NegativeInfinity,
+                Compact::Valid(offset) => Position::Valid(offset),
+                Compact::PositiveInfinity => Position::PositiveInfinity,
+            })
+        }
+    }
+}
+
+struct HumanReadable;
+
+impl Visitor<'_> for HumanReadable {
+    type Value = Position;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "an offset, {:?} or {:?}",
+            NEGATIVE_INFINITY, POSITIVE_INFINITY
+        )
+    }
+
+    fn visit_u64<E: de::Error>(self, offset: u64) -> Result<Position, E> {
+        Ok(Position::Valid(offset))
+    }
+
+    fn visit_i64<E: de::Error>(self, offset: i64) -> Result<Position, E> {
+        u64::try_from(offset)
+            .map(Position::Valid)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(offset), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Position, E> {
+        match s {
+            NEGATIVE_INFINITY => Ok(Position::NegativeInfinity),
+            POSITIVE_INFINITY => Ok(Position::PositiveInfinity),
+            _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+        }
+    }
+}
+
+// The operator of a term in formats that are not human-readable
+const TERM: &str = "T";
+
+impl Serialize for Query {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (operator, operands) = match self {
+            Query::Term(term) if serializer.is_human_readable() => {
+                return serializer.serialize_str(term)
+            }
+            Query::Term(_) => (TERM, 1),
+            Query::Empty => ("E", 0),
+            Query::Layer(_) => ("L", 1),
+            Query::Phrase(terms) => ("P", terms.len()),
+            _ => (
+                self.binary_operator().expect("Only binary queries remain"),
+                2,
+            ),
+        };
+
+        let mut seq = serializer.serialize_seq(Some(1 + operands))?;
+        seq.serialize_element(operator)?;
+        match self {
+            Query::Empty => {}
+            Query::Term(name) | Query::Layer(name) => seq.serialize_element(name)?,
+            Query::Phrase(terms) => {
+                for term in terms {
+                    seq.serialize_element(term)?;
+                }
+            }
+            _ => {
+                let (a, b) = self.children().expect("Operators have two operands");
+                seq.serialize_element(a)?;
+                seq.serialize_element(b)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Query {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(QueryVisitor {
+                human_readable: true,
+            })
+        } else {
+            deserializer.deserialize_seq(QueryVisitor {
+                human_readable: false,
+            })
+        }
+    }
+}
+
+struct QueryVisitor {
+    human_readable: bool,
+}
+
+impl<'de> Visitor<'de> for QueryVisitor {
+    type Value = Query;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a term or an operator sequence")
+    }
+
+    fn visit_str<E: de::Error>(self, term: &str) -> Result<Query, E> {
+        if self.human_readable {
+            Ok(Query::Term(term.to_string()))
+        } else {
+            Err(E::invalid_type(de::Unexpected::Str(term), &self))
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Query, A::Error> {
+        let operator: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+        let (query, expected) = match operator.as_str() {
+            TERM if !self.human_readable => (Query::Term(operand(&mut seq, &operator, 1, 0)?), 1),
+            "E" => (Query::Empty, 0),
+            "L" => (Query::Layer(operand(&mut seq, &operator, 1, 0)?), 1),
+            "P" => {
+                let mut terms = Vec::new();
+                while let Some(term) = seq.next_element()? {
+                    terms.push(term);
+                }
+                if terms.is_empty() {
+                    return Err(wrong_arity(&operator, 1, 0));
+                }
+                return Ok(Query::Phrase(terms));
+            }
+            _ => match Query::binary(&operator) {
+                Some(binary) => {
+                    let a = operand(&mut seq, &operator, 2, 0)?;
+                    let b = operand(&mut seq, &operator, 2, 1)?;
+                    (binary(Box::new(a), Box::new(b)), 2)
+                }
+                None => {
+                    return Err(de::Error::custom(format_args!(
+                        "unknown operator {:?}",
+                        operator
+                    )))
+                }
+            },
+        };
+
+        let mut extra = 0;
+        while seq.next_element::<IgnoredAny>()?.is_some() {
+            extra += 1;
+        }
+        if extra > 0 {
+            return Err(wrong_arity(&operator, expected, expected + extra));
+        }
+        Ok(query)
+    }
+}
+
+// The operand after the `read` operands already read, of an operator
+// taking `expected` operands
+fn operand<'de, A, T>(
+    seq: &mut A,
+    operator: &str,
+    expected: usize,
+    read: usize,
+) -> Result<T, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de>,
+{
+    seq.next_element()?
+        .ok_or_else(|| wrong_arity(operator, expected, read))
+}
+
+fn wrong_arity<E: de::Error>(operator: &str, expected: usize, found: usize) -> E {
+    E::custom(format_args!(
+        "operator {:?} takes {} operand(s), found {}",
+        operator, expected, found
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Deletions, Documents, Extent, Index, NestedLayer, OffsetUnit, Position, Query};
+    use serde_test::{assert_tokens, Configure, Token};
+
+    #[test]
+    fn deletions_are_counted_when_read() {
+        let deletions: Deletions = serde_json::from_str("[70, 3, 3]").unwrap();
+
+        assert_eq!(deletions.len(), 2);
+        assert!(deletions.contains(3) && deletions.contains(70));
+        assert!(serde_json::from_str::<Deletions>(r#"{"bits":[1],"len":0}"#).is_err());
+
+        let large: Deletions = serde_json::from_str("[4294967295]").unwrap();
+        assert!(large.contains(u32::MAX));
+        assert_eq!(large.len(), 1);
+    }
+
+    #[test]
+    fn positions_are_numbers_or_infinities_in_json() {
+        let extents = [
+            Extent(Position::NegativeInfinity, Position::Valid(3)),
+            Extent(Position::Valid(3), Position::PositiveInfinity),
+        ];
+
+        let json = serde_json::to_string(&extents).unwrap();
+        let back: Vec<Extent> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(json, r#"[["-inf",3],[3,"+inf"]]"#);
+        assert_eq!(back, extents);
+    }
+
+    #[test]
+    fn invalid_positions_are_rejected() {
+        assert!(serde_json::from_str::<Position>(r#""inf""#).is_err());
+        assert!(serde_json::from_str::<Position>("-1").is_err());
+        assert!(serde_json::from_str::<Position>("1.5").is_err());
+    }
+
+    #[test]
+    fn positions_are_enums_in_compact_formats() {
+        assert_tokens(
+            &Position::PositiveInfinity.compact(),
+            &[Token::UnitVariant {
+                name: "Position",
+                variant: "PositiveInfinity",
+            }],
+        );
+        assert_tokens(
+            &Position::Valid(7).compact(),
+            &[
+                Token::NewtypeVariant {
+                    name: "Position",
+                    variant: "Valid",
+                },
+                Token::U64(7),
+            ],
+        );
+        assert_tokens(
+            &Position::NegativeInfinity.readable(),
+            &[Token::Str("-inf")],
+        );
+    }
+
+    #[test]
+    fn queries_round_trip() {
+        let query = Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(Query::Layer("sentence".into())),
+        );
+
+        let json = serde_json::to_string(&query).unwrap();
+
+        assert_eq!(json, r#"["<","world",["L","sentence"]]"#);
+        assert_eq!(serde_json::from_str::<Query>(&json).unwrap(), query);
+        assert!(serde_json::from_str::<Query>(r#"["?", "a"]"#)
+            .unwrap_err()
+            .to_string()
+            .contains("unknown operator"));
+        assert!(serde_json::from_str::<Query>(r#"["<", "a"]"#)
+            .unwrap_err()
+            .to_string()
+            .contains("takes 2 operand(s), found 1"));
+        assert!(serde_json::from_str::<Query>(r#"["T", "a"]"#).is_err());
+    }
+
+    #[test]
+    fn queries_are_sequences_in_compact_formats() {
+        let query = Query::FollowedBy(
+            Box::new(Query::Term("a".into())),
+            Box::new(Query::Phrase(vec!["b".into(), "c".into()])),
+        );
+
+        assert_tokens(
+            &query.compact(),
+            &[
+                Token::Seq { len: Some(3) },
+                Token::Str("->"),
+                Token::Seq { len: Some(2) },
+                Token::Str("T"),
+                Token::Str("a"),
+                Token::SeqEnd,
+                Token::Seq { len: Some(3) },
+                Token::Str("P"),
+                Token::Str("b"),
+                Token::Str("c"),
+                Token::SeqEnd,
+                Token::SeqEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn lists_and_collections_round_trip() {
+        let mut index = Index::new();
+        index.extend_term("hello", vec![(0, 5), (10, 15)]);
+        index.extend_layer("sentence", vec![(0, 20)]);
+        let mut deletions = Deletions::new();
+        deletions.insert(3);
+
+        let index: Index = serde_json::from_str(&serde_json::to_string(&index).unwrap()).unwrap();
+        let nested = NestedLayer::new(vec![(0, 10), (2, 4)]);
+        let json = serde_json::to_string(&nested).unwrap();
+        assert_eq!(json, "[[0,10],[2,4]]");
+
+        assert_eq!(index.term("hello"), [(0, 5), (10, 15)]);
+        assert_eq!(index.layer("sentence"), [(0, 20)]);
+        assert_eq!(serde_json::from_str::<NestedLayer>(&json).unwrap(), nested);
+        assert_eq!(serde_json::to_string(&deletions).unwrap(), "[3]");
+        assert_eq!(
+            serde_json::from_str::<Deletions>(&serde_json::to_string(&deletions).unwrap()).unwrap(),
+            deletions
+        );
+        assert_eq!(
+            serde_json::to_string(&Documents::new(2)).unwrap(),
+            r#"{"count":2}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&OffsetUnit::Utf16).unwrap(),
+            r#""utf16""#
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    doc_and_offset_to_k, Algebra, Extent, Index, Position, Query, ValidExtent, DOC_OFFSET_MAX,
+    END_EXTENT, START_EXTENT,
+};
+use std::{error::Error, fmt, ops::Range, thread};
+
+/// A reason that a shard could not be added to a [`ShardedIndex`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShardError {
+    /// The document range is empty.
+    NoDocuments,
+    /// The document range overlaps with that of an existing shard.
+    OverlappingDocuments {
+        documents: Range<u32>,
+        existing: Range<u32>,
+    },
+    /// A posting list contains an extent outside of the shard's
+    /// documents.
+    ExtentOutsideDocuments {
+        documents: Range<u32>,
+        extent: ValidExtent,
+    },
+}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShardError::NoDocuments => "the shard has no documents".fmt(f),
+            ShardError::OverlappingDocuments {
+                documents,
+                existing,
+            } => write!(
+                f,
+                "documents {:?} overlap the existing shard with documents {:?}",
+                documents, existing
+            ),
+            ShardError::ExtentOutsideDocuments { documents, extent } => write!(
+                f,
+                "extent {:?} is outside of the shard's documents {:?}",
+                extent, documents
+            ),
+        }
+    }
+}
+
+impl Error for ShardError {}
+
+/// A portion of a [`ShardedIndex`] covering a contiguous range of
+/// documents.
+#[derive(Debug, Clone)]
+pub struct Shard {
+    documents: Range<u32>,
+    index: Index,
+}
+
+impl Shard {
+    pub fn documents(&self) -> Range<u32> {
+        self.documents.clone()
+    }
+
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    fn positions(&self) -> ValidExtent {
+        (
+            doc_and_offset_to_k(self.documents.start, 0),
+            doc_and_offset_to_k(self.documents.end - 1, DOC_OFFSET_MAX),
+        )
+    }
+}
+
+/// An index split into shards, each owning the posting lists for a
+/// disjoint range of documents.
+///
+/// Queries are evaluated on every shard separately and the results
+/// are combined in position order. Because no shard can see the
+/// extents of another, an extent found by a query never spans more
+/// than one shard: an extent that a single index would find across
+/// the boundary between two shards, such as a `FollowedBy` from the
+/// last document of one shard to the first of the next, is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct ShardedIndex {
+    // Ordered by document range
+    shards: Vec<Shard>,
+}
+
+impl ShardedIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a shard responsible for the given documents. Every extent
+    /// of the index must lie within those documents.
+    pub fn add_shard(&mut self, documents: Range<u32>, index: Index) -> Result<(), ShardError> {
+        if documents.start >= documents.end {
+            return Err(ShardError::NoDocuments);
+        }
+
+        let idx = self
+            .shards
+            .binary_search_by(|s| s.documents.start.cmp(&documents.start))
+            .unwrap_or_else(|idx| idx);
+
+        let neighbors = self.shards[idx.saturating_sub(1)..].iter().take(2);
+        for existing in neighbors {
+            let existing = &existing.documents;
+            if existing.start < documents.end && documents.start < existing.end {
+                return Err(ShardError::OverlappingDocuments {
+                    documents,
+                    existing: existing.clone(),
+                });
+            }
+        }
+
+        let shard = Shard { documents, index };
+
+        let (start, end) = shard.positions();
+        let lists = shard.index.terms().chain(shard.index.layers());
+        for (_, extents) in lists {
+            let outside = extents
+                .first()
+                .into_iter()
+                .chain(extents.last())
+                .find(|e| e.0 < start || e.1 > end);
+
+            if let Some(&extent) = outside {
+                return Err(ShardError::ExtentOutsideDocuments {
+                    documents: shard.documents,
+                    extent,
+                });
+            }
+        }
+
+        self.shards.insert(idx, shard);
+        Ok(())
+    }
+
+    /// The shards, in document order.
+    pub fn shards(&self) -> &[Shard] {
+        &self.shards
+    }
+
+    /// Finds all extents of the query, evaluating each shard on its
+    /// own thread.
+    ///
+    /// Every extent is found up front, with a new thread for each
+    /// shard on every call, so this suits queries whose results are
+    /// all needed. [`ShardedIndex::evaluate_lazy`] finds extents on
+    /// demand instead.
+    ///
+    /// The result is a GC-list ordered by position, so it may be
+    /// iterated forwards or backwards like any other extent list.
+    pub fn evaluate(&self, query: &Query) -> Vec<ValidExtent> {
+        let per_shard: Vec<Vec<ValidExtent>> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .shards
+                .iter()
+                .map(|shard| scope.spawn(move || query.evaluate(&shard.index).iter_tau().collect()))
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Evaluating a shard panicked"))
+                .collect()
+        });
+
+        // Shards cover disjoint, ordered positions, so their results
+        // are already in global order.
+        per_shard.concat()
+    }
+
+    /// Builds the lazily-evaluated operator tree for the query on
+    /// every shard, on the calling thread.
+    ///
+    /// Each access function asks only the shards that may hold the
+    /// answer, in position order, so that iterating over the first
+    /// few extents in either direction does not evaluate every shard.
+    pub fn evaluate_lazy(&self, query: &Query) -> Box<dyn Algebra + Send + Sync + '_> {
+        Box::new(ShardedExtents {
+            shards: self
+                .shards
+                .iter()
+                .map(|shard| (shard.positions(), query.evaluate(&shard.index)))
+                .collect(),
+        })
+    }
+}
+
+// The operator trees of each shard, with the positions each covers
+struct ShardedExtents<'a> {
+    shards: Vec<(ValidExtent, Box<dyn Algebra + Send + Sync + 'a>)>,
+}
+
+impl ShardedExtents<'_> {
+    // Asks each shard that ends at or after k, in order, until one
+    // has an answer
+    fn forwards<F>(&self, k: Position, find: F) -> Extent
+    where
+        F: Fn(&dyn Algebra) -> Extent,
+    {
+        let idx = self
+            .shards
+            .partition_point(|&((_, end), _)| Position::from(end) < k);
+        self.shards[idx..]
+            .iter()
+            .map(|(_, shard)| find(&**shard))
+            .find(|&e| e != END_EXTENT)
+            .unwrap_or(END_EXTENT)
+    }
+
+    // Asks each shard that starts at or before k, in reverse order,
+    // until one has an answer
+    fn backwards<F>(&self, k: Position, find: F) -> Extent
+    where
+        F: Fn(&dyn Algebra) -> Extent,
+    {
+        let idx = self
+            .shards
+            .partition_point(|&((start, _), _)| Position::from(start) <= k);
+        self.shards[..idx]
+            .iter()
+            .rev()
+            .map(|(_, shard)| find(&**shard))
+            .find(|&e| e != START_EXTENT)
+            .unwrap_or(START_EXTENT)
+    }
+}
+
+impl Algebra for ShardedExtents<'_> {
+    fn tau(&self, k: Position) -> Extent {
+        self.forwards(k, |shard| shard.tau(k))
+    }
+    fn tau_prime(&self, k: Position) -> Extent {
+        self.backwards(k, |shard| shard.tau_prime(k))
+    }
+    fn rho(&self, k: Position) -> Extent {
+        self.forwards(k, |shard| shard.rho(k))
+    }
+    fn rho_prime(&self, k: Position) -> Extent {
+        self.backwards(k, |shard| shard.rho_prime(k))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn doc_extent(doc: u32, start: u32, end: u32) -> ValidExtent {
+        (
+            doc_and_offset_to_k(doc, start),
+            doc_and_offset_to_k(doc, end),
+        )
+    }
+
+    // "hello world. goodbye world." in every document
+    fn index_documents(documents: Range<u32>) -> Index {
+        let mut index = Index::new();
+        for doc in documents {
+            index.extend_term("hello", vec![doc_extent(doc, 0, 5)]);
+            index.extend_term(
+                "world",
+                vec![doc_extent(doc, 6, 11), doc_extent(doc, 21, 26)],
+            );
+            index.extend_term("goodbye", vec![doc_extent(doc, 13, 20)]);
+            index.extend_layer(
+                "sentence",
+                vec![doc_extent(doc, 0, 12), doc_extent(doc, 13, 27)],
+            );
+        }
+        index
+    }
+
+    fn sharded(ranges: &[Range<u32>]) -> ShardedIndex {
+        let mut sharded = ShardedIndex::new();
+        for r in ranges {
+            sharded
+                .add_shard(r.clone(), index_documents(r.clone()))
+                .unwrap();
+        }
+        sharded
+    }
+
+    fn world_in_goodbye_sentence() -> Query {
+        Query::ContainedIn(
+            Box::new(Query::Term("world".into())),
+            Box::new(Query::Containing(
+                Box::new(Query::Layer("sentence".into())),
+                Box::new(Query::Term("goodbye".into())),
+            )),
+        )
+    }
+
+    #[test]
+    fn sharded_results_match_single_index() {
+        let sharded = sharded(&[4..10, 0..1, 1..4]);
+        let single = index_documents(0..10);
+        let query = world_in_goodbye_sentence();
+
+        let expected: Vec<_> = query.evaluate(&single).iter_tau().collect();
+        let found = sharded.evaluate(&query);
+
+        assert_eq!(found.len(), 10);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn sharded_results_iterate_in_both_directions() {
+        let sharded = sharded(&[0..3, 3..5]);
+        let single = index_documents(0..5);
+        let query = Query::Term("world".into());
+
+        let found = sharded.evaluate(&query);
+
+        assert!((&found).iter_tau().eq(query.evaluate(&single).iter_tau()));
+        assert!((&found)
+            .iter_tau_prime()
+            .eq(query.evaluate(&single).iter_tau_prime()));
+    }
+
+    #[test]
+    fn lazy_results_match_parallel_results() {
+        let sharded = sharded(&[0..1, 1..2, 2..6]);
+        let mut with_empty_shard = sharded.clone();
+        with_empty_shard.add_shard(8..9, Index::new()).unwrap();
+        let query = world_in_goodbye_sentence();
+
+        let found = sharded.evaluate(&query);
+
+        for sharded in &[sharded, with_empty_shard] {
+            let lazy = sharded.evaluate_lazy(&query);
+            assert!((&lazy).iter_tau().eq(found.iter().copied()));
+            assert!((&lazy).iter_rho().eq(found.iter().copied()));
+            assert!((&lazy).iter_tau_prime().eq(found.iter().rev().copied()));
+            assert!((&lazy).iter_rho_prime().eq(found.iter().rev().copied()));
+        }
+    }
+
+    #[test]
+    fn extents_across_shards_are_dropped() {
+        let sharded = sharded(&[0..2, 2..4]);
+        let single = index_documents(0..4);
+        let query = Query::FollowedBy(
+            Box::new(Query::Term("goodbye".into())),
+            Box::new(Query::Term("hello".into())),
+        );
+
+        assert_eq!(query.evaluate(&single).iter_tau().count(), 3);
+        assert_eq!(sharded.evaluate(&query).len(), 2);
+        assert_eq!(sharded.evaluate_lazy(&query).iter_tau().count(), 2);
+    }
+
+    #[test]
+    fn shards_are_kept_in_document_order() {
+        let sharded = sharded(&[5..6, 0..2, 2..5]);
+        let starts: Vec<_> = sharded
+            .shards()
+            .iter()
+            .map(|s| s.documents().start)
+            .collect();
+
+        assert_eq!(starts, [0, 2, 5]);
+    }
+
+    #[test]
+    fn overlapping_shards_are_rejected() {
+        let mut sharded = sharded(&[0..3, 5..8]);
+
+        let err = sharded.add_shard(2..6, Index::new()).unwrap_err();
+
+        assert_eq!(
+            err,
+            ShardError::OverlappingDocuments {
+                documents: 2..6,
+                existing: 0..3,
+            }
+        );
+    }
+
+    #[test]
+    fn extents_outside_of_the_shard_are_rejected() {
+        let mut sharded = ShardedIndex::new();
+        let mut index = Index::new();
+        index.extend_term("hello", vec![doc_extent(1, 0, 5), doc_extent(2, 0, 5)]);
+
+        let err = sharded.add_shard(0..2, index).unwrap_err();
+
+        assert_eq!(
+            err,
+            ShardError::ExtentOutsideDocuments {
+                documents: 0..2,
+                extent: doc_extent(2, 0, 5),
+            }
+        );
+    }
+}
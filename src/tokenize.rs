@@ -0,0 +1,353 @@
+use crate::{Index, ValidExtent};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The layer holding every token of a text, whatever its term, as
+/// recorded by [`Tokenizer::index`] and [`Tokenizer::index_tokens`].
+///
+/// A [`Query::Phrase`](crate::Query::Phrase) uses it to tell that
+/// two terms are consecutive tokens. Only the tokens that are indexed
+/// are recorded, so a token dropped by a filter such as
+/// [`StopWords`](crate::StopWords) does not separate its neighbours.
+pub const TOKEN_LAYER: &str = "#token";
+
+/// Splits text into the terms to be indexed.
+///
+/// Each term is paired with the extent of the text it came from. The
+/// extents are byte offsets into the text, with an exclusive end, and
+/// are produced in order.
+pub trait Tokenizer {
+    fn tokenize<'t>(&self, text: &'t str) -> Box<dyn Iterator<Item = (String, ValidExtent)> + 't>;
+
+    /// Builds the index of the terms of the text, along with the
+    /// [`TOKEN_LAYER`].
+    fn index(&self, text: &str) -> Index {
+        let mut index = Index::new();
+        let mut tokens = Vec::new();
+        for (term, extent) in self.tokenize(text) {
+            index.extend_term(term, Some(extent));
+            tokens.push(extent);
+        }
+        index.extend_layer(TOKEN_LAYER, tokens);
+        index
+    }
+
+    /// Builds the index of the terms of the text, using the ordinal
+    /// of each token as its position instead of its byte offset.
+    ///
+    /// The extent of the n-th token is `(n, n)`, so an extent covers
+    /// every token from its start to its end, inclusive, and adjacent
+    /// tokens can be found with [`FollowedBy`][crate::FollowedBy]. The
+    /// returned offsets map token positions back to the text.
+    fn index_tokens(&self, text: &str) -> (Index, TokenOffsets) {
+        let mut index = Index::new();
+        let mut offsets = TokenOffsets::default();
+        for (term, extent) in self.tokenize(text) {
+            let position = offsets.len() as u64;
+            index.extend_term(term, Some((position, position)));
+            offsets.bytes.push(extent);
+        }
+        let positions = 0..offsets.len() as u64;
+        index.extend_layer(TOKEN_LAYER, positions.map(|p| (p, p)));
+        (index, offsets)
+    }
+}
+
+impl<T> Tokenizer for &T
+where
+    T: Tokenizer + ?Sized,
+{
+    fn tokenize<'t>(&self, text: &'t str) -> Box<dyn Iterator<Item = (String, ValidExtent)> + 't> {
+        (**self).tokenize(text)
+    }
+}
+
+impl<T> Tokenizer for Box<T>
+where
+    T: Tokenizer + ?Sized,
+{
+    fn tokenize<'t>(&self, text: &'t str) -> Box<dyn Iterator<Item = (String, ValidExtent)> + 't> {
+        (**self).tokenize(text)
+    }
+}
+
+/// Finds contiguous runs of alphabetic characters and downcases them.
+///
+/// No smart language processing is performed; digits and punctuation
+/// separate terms and are never indexed themselves.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AlphabeticTokenizer;
+
+impl Tokenizer for AlphabeticTokenizer {
+    fn tokenize<'t>(&self, text: &'t str) -> Box<dyn Iterator<Item = (String, ValidExtent)> + 't> {
+        let mut chars = text.char_indices().peekable();
+
+        Box::new(std::iter::from_fn(move || {
+            while chars.next_if(|&(_, c)| !c.is_alphabetic()).is_some() {}
+
+            let (start, first) = chars.next()?;
+            let mut end = start + first.len_utf8();
+            while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_alphabetic()) {
+                end = i + c.len_utf8();
+            }
+
+            let term = text[start..end].to_lowercase();
+            Some((term, (start as u64, end as u64)))
+        }))
+    }
+}
+
+/// Finds words using the Unicode word boundary rules of [UAX #29][]
+/// and downcases them.
+///
+/// Numbers such as `3.14` are kept whole, hyphenated words are split
+/// into their parts, and each ideograph of CJK text becomes its own
+/// term.
+///
+/// Consecutive ideographs have byte extents that touch, and
+/// [`FollowedBy`](crate::FollowedBy) requires a gap between the end
+/// of one extent and the start of the next, so it cannot find a run
+/// of ideographs in an index built by [`Tokenizer::index`]. Search for
+/// such a run with a [`Query::Phrase`](crate::Query::Phrase), which
+/// [`Query::analyze`](crate::Query::analyze) builds from a term such
+/// as `東京`, or index token positions with
+/// [`Tokenizer::index_tokens`].
+///
+/// [UAX #29]: https://www.unicode.org/reports/tr29/
+#[derive(Debug, Copy, Clone, Default)]
+pub struct UnicodeWordTokenizer;
+
+impl Tokenizer for UnicodeWordTokenizer {
+    fn tokenize<'t>(&self, text: &'t str) -> Box<dyn Iterator<Item = (String, ValidExtent)> + 't> {
+        Box::new(text.unicode_word_indices().map(|(start, word)| {
+            let end = start + word.len();
+            (word.to_lowercase(), (start as u64, end as u64))
+        }))
+    }
+}
+
+/// The byte extent of each token of a text, indexed by token
+/// position.
+///
+/// Created by [`Tokenizer::index_tokens`] to translate between token
+/// positions and the byte offsets of the text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TokenOffsets {
+    // Ordered by position; tokens never overlap
+    bytes: Vec<ValidExtent>,
+}
+
+impl TokenOffsets {
+    /// The number of tokens.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The bytes covered by an extent of token positions, from the
+    /// start of its first token to the end of its last token.
+    pub fn byte_extent(&self, tokens: ValidExtent) -> Option<ValidExtent> {
+        let (first, last) = (tokens.0 as usize, tokens.1 as usize);
+        if first > last || last >= self.len() {
+            return None;
+        }
+        Some((self.bytes[first].0, self.bytes[last].1))
+    }
+
+    /// The token positions of the tokens lying entirely within an
+    /// extent of bytes, or `None` if there are none.
+    pub fn token_extent(&self, bytes: ValidExtent) -> Option<ValidExtent> {
+        let first = self.bytes.partition_point(|t| t.0 < bytes.0);
+        let end = self.bytes.partition_point(|t| t.1 <= bytes.1);
+        if first < end {
+            Some((first as u64, end as u64 - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Converts a GC-list of byte extents, such as a layer, to token
+    /// positions.
+    ///
+    /// Extents that contain no tokens are dropped, as are extents that
+    /// would no longer keep the list a GC-list, such as two sentences
+    /// covering the same tokens.
+    pub fn token_extents<I>(&self, bytes: I) -> Vec<ValidExtent>
+    where
+        I: IntoIterator<Item = ValidExtent>,
+    {
+        let mut extents: Vec<ValidExtent> = Vec::new();
+        for extent in bytes.into_iter().filter_map(|e| self.token_extent(e)) {
+            if extents
+                .last()
+                .is_none_or(|last| last.0 < extent.0 && last.1 < extent.1)
+            {
+                extents.push(extent);
+            }
+        }
+        extents
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens<T: Tokenizer>(tokenizer: T, text: &str) -> Vec<(String, ValidExtent)> {
+        tokenizer.tokenize(text).collect()
+    }
+
+    fn token(term: &str, start: u64, end: u64) -> (String, ValidExtent) {
+        (term.to_string(), (start, end))
+    }
+
+    #[test]
+    fn alphabetic_splits_on_non_alphabetic_characters() {
+        assert_eq!(
+            tokens(AlphabeticTokenizer, "Hello, world! Goodbye, world!"),
+            [
+                token("hello", 0, 5),
+                token("world", 7, 12),
+                token("goodbye", 14, 21),
+                token("world", 23, 28),
+            ]
+        );
+    }
+
+    #[test]
+    fn alphabetic_drops_numbers_and_splits_hyphens() {
+        assert_eq!(
+            tokens(AlphabeticTokenizer, "a well-known 42x"),
+            [
+                token("a", 0, 1),
+                token("well", 2, 6),
+                token("known", 7, 12),
+                token("x", 15, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn alphabetic_ends_extents_after_multibyte_characters() {
+        let text = "café öl";
+        let found = tokens(AlphabeticTokenizer, text);
+
+        assert_eq!(found, [token("café", 0, 5), token("öl", 6, 9)]);
+        assert_eq!(&text[6..9], "öl");
+    }
+
+    #[test]
+    fn unicode_words_keep_numbers() {
+        assert_eq!(
+            tokens(UnicodeWordTokenizer, "Pi is 3.14, not 3!"),
+            [
+                token("pi", 0, 2),
+                token("is", 3, 5),
+                token("3.14", 6, 10),
+                token("not", 12, 15),
+                token("3", 16, 17),
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_words_split_hyphenated_words() {
+        assert_eq!(
+            tokens(UnicodeWordTokenizer, "Well-known"),
+            [token("well", 0, 4), token("known", 5, 10)]
+        );
+    }
+
+    #[test]
+    fn unicode_words_split_ideographs() {
+        let text = "東京に行く";
+        let found = tokens(UnicodeWordTokenizer, text);
+
+        assert_eq!(found[0], token("東", 0, 3));
+        assert_eq!(found[1], token("京", 3, 6));
+        assert!(found
+            .iter()
+            .all(|(t, e)| &text[e.0 as usize..e.1 as usize] == t));
+    }
+
+    #[test]
+    fn index_records_every_token() {
+        let index = UnicodeWordTokenizer.index("東京 3.14");
+        let (positions, _) = UnicodeWordTokenizer.index_tokens("東京 3.14");
+
+        assert_eq!(index.layer(TOKEN_LAYER), [(0, 3), (3, 6), (7, 11)]);
+        assert_eq!(positions.layer(TOKEN_LAYER), [(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn index_collects_every_occurrence() {
+        let index = AlphabeticTokenizer.index("Hello, world! Goodbye, world!");
+
+        assert_eq!(index.term("world"), [(7, 12), (23, 28)]);
+        assert_eq!(index.term("hello"), [(0, 5)]);
+        assert_eq!(index.term("missing"), []);
+    }
+
+    #[test]
+    fn token_positions_count_tokens() {
+        let (index, offsets) = AlphabeticTokenizer.index_tokens("Hello, world! Goodbye, world!");
+
+        assert_eq!(offsets.len(), 4);
+        assert_eq!(index.term("hello"), [(0, 0)]);
+        assert_eq!(index.term("world"), [(1, 1), (3, 3)]);
+        assert_eq!(index.term("goodbye"), [(2, 2)]);
+    }
+
+    #[test]
+    fn token_positions_map_back_to_bytes() {
+        let text = "Hello, world! Goodbye, world!";
+        let (_, offsets) = AlphabeticTokenizer.index_tokens(text);
+
+        assert_eq!(offsets.byte_extent((1, 1)), Some((7, 12)));
+        assert_eq!(offsets.byte_extent((0, 3)), Some((0, 28)));
+        assert_eq!(offsets.byte_extent((2, 1)), None);
+        assert_eq!(offsets.byte_extent((3, 4)), None);
+    }
+
+    #[test]
+    fn byte_extents_map_to_contained_tokens() {
+        let (_, offsets) = AlphabeticTokenizer.index_tokens("Hello, world! Goodbye, world!");
+
+        assert_eq!(offsets.token_extent((0, 13)), Some((0, 1)));
+        assert_eq!(offsets.token_extent((14, 29)), Some((2, 3)));
+        assert_eq!(offsets.token_extent((1, 12)), Some((1, 1)));
+        assert_eq!(offsets.token_extent((5, 7)), None);
+    }
+
+    #[test]
+    fn layers_are_converted_to_token_positions() {
+        let (_, offsets) = AlphabeticTokenizer.index_tokens("Hello, world! Goodbye, world!");
+
+        let sentences = offsets.token_extents(vec![(0, 13), (13, 14), (14, 29)]);
+        assert_eq!(sentences, [(0, 1), (2, 3)]);
+
+        // Both extents cover only "hello"
+        let overlapping = offsets.token_extents(vec![(0, 5), (0, 6)]);
+        assert_eq!(overlapping, [(0, 0)]);
+    }
+
+    #[test]
+    fn followed_by_spans_adjacent_tokens() {
+        use crate::{Algebra, Query};
+
+        let text = "Hello, world! Goodbye, world!";
+        let (index, offsets) = AlphabeticTokenizer.index_tokens(text);
+        let query = Query::FollowedBy(
+            Box::new(Query::Term("goodbye".into())),
+            Box::new(Query::Term("world".into())),
+        );
+
+        let found: Vec<_> = query.evaluate(&index).iter_tau().collect();
+
+        assert_eq!(found, [(2, 3)]);
+        assert_eq!(offsets.byte_extent(found[0]), Some((14, 28)));
+    }
+}